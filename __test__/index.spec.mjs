@@ -1,10 +1,30 @@
 import test from "ava";
+import { getEventListeners } from "node:events";
+import { inspect } from "node:util";
 import {
+  Keyring,
+  configure,
   deletePassword,
+  findByAccount,
   findCredentials,
   findPassword,
+  getCacheStats,
+  getJSON,
   getPassword,
+  getPasswordHistory,
+  getSecretHandle,
+  iterateCredentials,
+  patchJSON,
+  purgeExpired,
+  rollbackPassword,
+  rotatePassword,
+  searchCredentials,
+  setJSON,
   setPassword,
+  swapPassword,
+  takePassword,
+  verifyPassword,
+  watch,
 } from "../index.js";
 
 // generate a number in range [min, max)
@@ -127,6 +147,47 @@ test.serial(
   }
 );
 
+test.serial("getPassword with prompts disabled on an unlocked keyring", async (t) => {
+  const str = await getPassword("TestKeytar", "TestASCII", { allowPrompt: false });
+  t.is(str, "ASCII string");
+});
+
+test.serial("getPassword rejects with AbortError for an aborted signal", async (t) => {
+  const controller = new AbortController();
+  controller.abort();
+  const err = await t.throwsAsync(
+    getPassword("TestKeytar", "TestASCII", { signal: controller.signal })
+  );
+  t.is(err.name, "AbortError");
+  t.is(err.code, "ABORT_ERR");
+});
+
+test.serial("settled calls remove their abort listener", async (t) => {
+  const controller = new AbortController();
+  for (let i = 0; i < 3; i++) {
+    await getPassword("TestKeytar", "TestASCII", { signal: controller.signal });
+  }
+  t.is(getEventListeners(controller.signal, "abort").length, 0);
+});
+
+test.serial("getPassword completes within its timeout", async (t) => {
+  const str = await getPassword("TestKeytar", "TestASCII", { timeout: 30000 });
+  t.is(str, "ASCII string");
+});
+
+test.serial("getPassword rejects with ETIMEDOUT once its timeout elapses", async (t) => {
+  // With a single worker, the call waits in the queue behind the others until its timeout elapses
+  configure({ concurrency: 1 });
+  try {
+    const queued = Array.from({ length: 50 }, () => getPassword("TestKeytar", "TestASCII"));
+    const err = await t.throwsAsync(getPassword("TestKeytar", "TestASCII", { timeout: 1 }));
+    t.is(err.code, "ETIMEDOUT");
+    await Promise.all(queued);
+  } finally {
+    configure({ concurrency: 2 });
+  }
+});
+
 test.serial("getPassword with missing data", async (t) => {
   const str = await getPassword("TestKeytar", "TestMissingPW");
   t.is(str, null);
@@ -159,6 +220,333 @@ test.serial(
   }
 );
 
+test.serial("findCredentials reports skipped items in warnings", async (t) => {
+  const creds = await findCredentials("TestKeytar");
+  t.true(Array.isArray(creds.warnings));
+  t.false(Object.keys(creds).includes("warnings"));
+});
+
+test.serial("findCredentials with limit and offset", async (t) => {
+  const all = await findCredentials("TestKeytar");
+  const first = await findCredentials("TestKeytar", { limit: 3 });
+  const rest = await findCredentials("TestKeytar", { offset: 3 });
+  t.is(first.length, 3);
+  t.is(first.length + rest.length, all.length);
+});
+
+test.serial("findCredentials sorted by account with dedupe", async (t) => {
+  const creds = await findCredentials("TestKeytar", { sort: "account", dedupe: true });
+  const accounts = creds.map((c) => c.account);
+  t.deepEqual(accounts, [...new Set(accounts)].sort());
+});
+
+test.serial("iterateCredentials yields every credential in pages", async (t) => {
+  const all = await findCredentials("TestKeytar");
+  const accounts = [];
+  for await (const cred of iterateCredentials("TestKeytar", { pageSize: 2 })) {
+    accounts.push(cred.account);
+  }
+  t.deepEqual(accounts.sort(), all.map((c) => c.account).sort());
+});
+
+test.serial("iterateCredentials rejects with AbortError once its signal aborts", async (t) => {
+  const controller = new AbortController();
+  const iterator = iterateCredentials("TestKeytar", { pageSize: 1, signal: controller.signal });
+  controller.abort();
+  const err = await t.throwsAsync(iterator.next());
+  t.is(err.name, "AbortError");
+});
+
+test.serial("findPassword with a slash in the account name", async (t) => {
+  await setPassword("TestKeytarSlash", "team/account", "slashPW");
+  t.is(await findPassword({ service: "TestKeytarSlash", account: "team/account" }), "slashPW");
+  t.is(await findPassword("TestKeytarSlash/team%2Faccount"), "slashPW");
+  t.true(await deletePassword("TestKeytarSlash", "team/account"));
+});
+
+test.serial("identifier policy normalizes service and account names", async (t) => {
+  configure({ identifiers: { normalize: true, caseInsensitive: true, trim: true } });
+  try {
+    await setPassword("TestKeytarCafe\u0301", " Account ", "normalizedPW");
+    t.is(await getPassword("testkeytarcaf\u00e9", "account"), "normalizedPW");
+    t.true(await deletePassword("TESTKEYTARCAFÉ", "ACCOUNT"));
+  } finally {
+    configure({ identifiers: {} });
+  }
+});
+
+test.serial("identifier policy finds credentials stored before it was enabled", async (t) => {
+  await setPassword("TestKeytarLegacyCafe\u0301", "Account", "legacyPW");
+  configure({ identifiers: { normalize: true, caseInsensitive: true } });
+  try {
+    t.is(await getPassword("testkeytarlegacycaf\u00e9", "account"), "legacyPW");
+    t.true(await deletePassword("TestKeytarLegacyCaf\u00e9", "ACCOUNT"));
+  } finally {
+    configure({ identifiers: {} });
+  }
+  t.is(await getPassword("TestKeytarLegacyCafe\u0301", "Account"), null);
+});
+
+test.serial("Keyring reads and writes credentials of its service", async (t) => {
+  const keyring = new Keyring({ service: "TestKeyring", labelTemplate: "Test {account}" });
+  t.is(keyring.service, "TestKeyring");
+
+  await keyring.set("AccountA", "keyringPW");
+  t.is(await keyring.get("AccountA"), "keyringPW");
+  t.is(await getPassword("TestKeyring", "AccountA"), "keyringPW");
+  t.deepEqual(await keyring.find(), [{ account: "AccountA", password: "keyringPW" }]);
+  t.true(await keyring.delete("AccountA"));
+});
+
+test.serial("Keyring rejects a backend of another platform", (t) => {
+  t.throws(() => new Keyring({ service: "TestKeyring", backend: "unknown" }));
+});
+
+test.serial("setPassword writes conditionally with ifAbsent and ifMatch", async (t) => {
+  t.true(await setPassword("TestKeytarConditional", "account", "first", { ifAbsent: true }));
+  t.false(await setPassword("TestKeytarConditional", "account", "second", { ifAbsent: true }));
+  t.false(await setPassword("TestKeytarConditional", "account", "second", { ifMatch: "wrong" }));
+  t.true(await setPassword("TestKeytarConditional", "account", "second", { ifMatch: "first" }));
+  t.is(await getPassword("TestKeytarConditional", "account"), "second");
+  t.true(await deletePassword("TestKeytarConditional", "account"));
+});
+
+test.serial("swapPassword and takePassword return the previous password", async (t) => {
+  t.is(await swapPassword("TestKeytarSwap", "account", "first"), null);
+  t.is(await swapPassword("TestKeytarSwap", "account", "second"), "first");
+  t.is(await takePassword("TestKeytarSwap", "account"), "second");
+  t.is(await takePassword("TestKeytarSwap", "account"), null);
+  t.is(await getPassword("TestKeytarSwap", "account"), null);
+});
+
+test.serial("expired credentials are treated as absent and purged", async (t) => {
+  if (process.platform === "darwin") {
+    await t.throwsAsync(setPassword("TestKeytarExpiry", "expired", "expiredPW", { ttl: 0 }));
+    return;
+  }
+
+  await setPassword("TestKeytarExpiry", "expired", "expiredPW", { expiresAt: Date.now() - 1000 });
+  await setPassword("TestKeytarExpiry", "valid", "validPW", { ttl: 60000 });
+  t.is(await getPassword("TestKeytarExpiry", "expired"), null);
+  t.is(await getPassword("TestKeytarExpiry", "valid"), "validPW");
+  t.deepEqual(await findCredentials("TestKeytarExpiry"), [{ account: "valid", password: "validPW" }]);
+  t.is(await purgeExpired("TestKeytarExpiry"), 1);
+  t.true(await deletePassword("TestKeytarExpiry", "valid"));
+});
+
+test.serial("history keeps previous passwords for rollback", async (t) => {
+  configure({ history: 2 });
+  try {
+    await setPassword("TestKeytarHistory", "account", "v1");
+    await setPassword("TestKeytarHistory", "account", "v2");
+    await setPassword("TestKeytarHistory", "account", "v3");
+    t.deepEqual(await getPasswordHistory("TestKeytarHistory", "account"), [
+      { version: 1, password: "v2" },
+      { version: 2, password: "v1" },
+    ]);
+    t.deepEqual(await findCredentials("TestKeytarHistory"), [{ account: "account", password: "v3" }]);
+
+    t.true(await rollbackPassword("TestKeytarHistory", "account", 2));
+    t.is(await getPassword("TestKeytarHistory", "account"), "v1");
+    t.true(await deletePassword("TestKeytarHistory", "account"));
+    t.deepEqual(await getPasswordHistory("TestKeytarHistory", "account"), []);
+  } finally {
+    configure({ history: 0 });
+  }
+});
+
+test.serial("configure keeps settings that are not given", async (t) => {
+  configure({ history: 1 });
+  configure({ concurrency: 2 });
+  try {
+    await setPassword("TestKeytarConfigure", "account", "v1");
+    await setPassword("TestKeytarConfigure", "account", "v2");
+    t.deepEqual(await getPasswordHistory("TestKeytarConfigure", "account"), [{ version: 1, password: "v1" }]);
+    t.true(await deletePassword("TestKeytarConfigure", "account"));
+  } finally {
+    configure({ history: 0 });
+  }
+});
+
+test.serial("rotatePassword stores the new password unless it changed meanwhile", async (t) => {
+  await setPassword("TestKeytarRotate", "account", "old");
+  await rotatePassword("TestKeytarRotate", "account", async (oldPassword) => `${oldPassword}-rotated`);
+  t.is(await getPassword("TestKeytarRotate", "account"), "old-rotated");
+
+  const err = await t.throwsAsync(
+    rotatePassword("TestKeytarRotate", "account", async () => {
+      await setPassword("TestKeytarRotate", "account", "concurrent");
+      return "lost";
+    }),
+  );
+  t.is(err.code, "ERR_KEYTAR_CONFLICT");
+  t.is(await getPassword("TestKeytarRotate", "account"), "concurrent");
+  t.true(await deletePassword("TestKeytarRotate", "account"));
+});
+
+test.serial("rotatePassword stores the new password once the callback has returned it", async (t) => {
+  await setPassword("TestKeytarRotate", "account", "old");
+  const controller = new AbortController();
+  // Neither the time taken by the callback nor an abort after it returned cancel the commit
+  await rotatePassword(
+    "TestKeytarRotate",
+    "account",
+    async () => {
+      await new Promise((resolve) => setTimeout(resolve, 100));
+      setTimeout(() => controller.abort(), 0);
+      return "new";
+    },
+    { signal: controller.signal, timeout: 50 },
+  );
+  t.is(await getPassword("TestKeytarRotate", "account"), "new");
+  t.is(getEventListeners(controller.signal, "abort").length, 0);
+  t.true(await deletePassword("TestKeytarRotate", "account"));
+});
+
+test.serial("searchCredentials finds credentials by tags and attributes", async (t) => {
+  if (process.platform === "darwin") {
+    await t.throwsAsync(setPassword("TestKeytarTags", "tagged", "taggedPW", { tags: { env: "prod" } }));
+    return;
+  }
+
+  await setPassword("TestKeytarTags", "prod", "prodPW", { tags: { env: "prod", team: "payments" } });
+  await setPassword("TestKeytarTags", "dev", "devPW", { tags: { env: "dev", team: "payments" } });
+  t.deepEqual(await searchCredentials({ tags: { env: "prod", team: "payments" } }), [
+    { service: "TestKeytarTags", account: "prod", password: "prodPW", tags: { env: "prod", team: "payments" } },
+  ]);
+  const found = await searchCredentials({ attributes: { service: "TestKeytarTags" }, tags: { team: "payments" } });
+  t.deepEqual(found.map((cred) => cred.account).sort(), ["dev", "prod"]);
+  t.true(await deletePassword("TestKeytarTags", "prod"));
+  t.true(await deletePassword("TestKeytarTags", "dev"));
+});
+
+test.serial("findByAccount finds the credentials of an account across services", async (t) => {
+  await setPassword("TestKeytarByAccountA", "leaver@example.com", "passwordA");
+  await setPassword("TestKeytarByAccountB", "leaver@example.com", "passwordB");
+  await setPassword("TestKeytarByAccountB", "leaver@example.org", "passwordC");
+
+  const found = await findByAccount("leaver@example.com");
+  t.deepEqual(found.map((cred) => cred.service).sort(), ["TestKeytarByAccountA", "TestKeytarByAccountB"]);
+  t.true(found.every((cred) => cred.password === undefined));
+  const prefixed = await findByAccount("leaver@", { prefix: true, secrets: true });
+  t.deepEqual(prefixed.map((cred) => cred.password).sort(), ["passwordA", "passwordB", "passwordC"]);
+
+  t.true(await deletePassword("TestKeytarByAccountA", "leaver@example.com"));
+  t.true(await deletePassword("TestKeytarByAccountB", "leaver@example.com"));
+  t.true(await deletePassword("TestKeytarByAccountB", "leaver@example.org"));
+});
+
+test.serial("watch reports changes to credentials", async (t) => {
+  if (process.platform !== "linux") {
+    const err = await t.throwsAsync(watch("TestKeytarWatch", () => {}));
+    t.is(err.code, "ERR_KEYTAR_UNSUPPORTED");
+    return;
+  }
+
+  const changes = [];
+  const dispose = await watch("TestKeytarWatch", (change) => changes.push(change));
+  try {
+    await setPassword("TestKeytarWatch", "account", "password");
+    await deletePassword("TestKeytarWatch", "account");
+    await setPassword("TestKeytarWatchOther", "account", "password");
+    await deletePassword("TestKeytarWatchOther", "account");
+    // Signals arrive asynchronously, after the calls have completed
+    await new Promise((resolve) => setTimeout(resolve, 500));
+  } finally {
+    dispose();
+  }
+
+  t.deepEqual(changes.at(0), { type: "created", service: "TestKeytarWatch", account: "account" });
+  t.deepEqual(changes.at(-1), { type: "deleted", service: "TestKeytarWatch", account: "account" });
+  t.true(changes.every((change) => change.service === "TestKeytarWatch"));
+});
+
+test.serial("cache serves repeated reads until the credential is written", async (t) => {
+  configure({ cache: { ttl: 60000 } });
+  try {
+    await setPassword("TestKeytarCache", "account", "first");
+    const before = getCacheStats();
+    t.is(await getPassword("TestKeytarCache", "account"), "first");
+    t.is(await getPassword("TestKeytarCache", "account"), "first");
+    const after = getCacheStats();
+    t.is(after.misses - before.misses, 1);
+    t.is(after.hits - before.hits, 1);
+
+    await setPassword("TestKeytarCache", "account", "second");
+    t.is(await getPassword("TestKeytarCache", "account"), "second");
+    t.true(await deletePassword("TestKeytarCache", "account"));
+    t.is(await getPassword("TestKeytarCache", "account"), null);
+  } finally {
+    configure({ cache: { ttl: 0 } });
+  }
+});
+
+test.serial("cache stops serving a credential once it expires", async (t) => {
+  if (process.platform === "darwin") {
+    t.pass("Credentials that expire are not supported by the macOS keychain");
+    return;
+  }
+
+  configure({ cache: { ttl: 60000 } });
+  try {
+    await setPassword("TestKeytarCacheExpiry", "account", "shortLived", { ttl: 200 });
+    t.is(await getPassword("TestKeytarCacheExpiry", "account"), "shortLived");
+    await new Promise((resolve) => setTimeout(resolve, 300));
+    t.is(await getPassword("TestKeytarCacheExpiry", "account"), null);
+    t.is(await purgeExpired("TestKeytarCacheExpiry"), 1);
+  } finally {
+    configure({ cache: { ttl: 0 } });
+  }
+});
+
+test.serial("getSecretHandle keeps the password until it is disposed", async (t) => {
+  t.is(await getSecretHandle("TestKeytarHandle", "account"), null);
+
+  await setPassword("TestKeytarHandle", "account", "password");
+  try {
+    const handle = await getSecretHandle("TestKeytarHandle", "account");
+    t.is(String(handle), "SecretHandle <redacted>");
+    t.false(inspect(handle).includes("password"));
+    t.true(handle.equals("password"));
+    t.false(handle.equals("other"));
+    t.true(handle.equals(Buffer.from("password")));
+    t.true(handle.equals(await getSecretHandle("TestKeytarHandle", "account")));
+    t.deepEqual(handle.toBuffer(), Buffer.from("password"));
+    t.is(handle.reveal(), "password");
+
+    handle.dispose();
+    t.is(String(handle), "SecretHandle <disposed>");
+    t.throws(() => handle.reveal());
+  } finally {
+    await deletePassword("TestKeytarHandle", "account");
+  }
+});
+
+test.serial("verifyPassword compares a candidate with the stored password", async (t) => {
+  t.false(await verifyPassword("TestKeytarVerify", "account", "password"));
+
+  await setPassword("TestKeytarVerify", "account", "password");
+  try {
+    t.true(await verifyPassword("TestKeytarVerify", "account", "password"));
+    t.false(await verifyPassword("TestKeytarVerify", "account", "Password"));
+    t.false(await verifyPassword("TestKeytarVerify", "account", "password2"));
+    t.false(await verifyPassword("TestKeytarVerify", "account", ""));
+  } finally {
+    await deletePassword("TestKeytarVerify", "account");
+  }
+});
+
+test.serial("patchJSON merges fields into a JSON value", async (t) => {
+  await setJSON("TestKeytarJSON", "oauth", { accessToken: "a1", refreshToken: "r1", scopes: ["read"] });
+  t.deepEqual(await patchJSON("TestKeytarJSON", "oauth", { accessToken: "a2", refreshToken: null }), {
+    accessToken: "a2",
+    scopes: ["read"],
+  });
+  t.deepEqual(await getJSON("TestKeytarJSON", "oauth"), { accessToken: "a2", scopes: ["read"] });
+  t.true(await deletePassword("TestKeytarJSON", "oauth"));
+  t.is(await getJSON("TestKeytarJSON", "oauth"), null);
+});
+
 test.serial("findPassword for ASCII string", async (t) => {
   const pw = await findPassword("TestKeytar/TestASCII");
   t.is(pw, "ASCII string");
@@ -0,0 +1,45 @@
+import os from "os";
+import { performance } from "perf_hooks";
+import {
+  deletePassword,
+  findCredentials,
+  getPassword,
+  setPassword,
+} from "../index.js";
+
+// Number of sequential calls measured per operation
+const ITERATIONS = Number(process.env.BENCH_ITERATIONS ?? 200);
+const SERVICE = "TestKeytarBenchmark";
+
+const bench = async (name, fn) => {
+  // Warm up once so that connecting to the keyring is not part of the measurement
+  await fn(0);
+
+  const start = performance.now();
+  for (let i = 0; i < ITERATIONS; i++) {
+    await fn(i);
+  }
+  const elapsed = performance.now() - start;
+  console.log(
+    `${name.padEnd(16)} ${(elapsed / ITERATIONS).toFixed(3)} ms/op (${ITERATIONS} ops)`
+  );
+};
+
+// Figures are only comparable between runs on the same platform
+console.log(
+  `${os.type()} ${os.release()} ${os.arch()}, ${os.cpus()[0]?.model ?? "unknown CPU"}, Node.js ${process.version}`
+);
+
+await setPassword(SERVICE, "account", "password");
+for (let i = 0; i < 10; i++) {
+  await setPassword(SERVICE, `account${i}`, "password");
+}
+
+await bench("setPassword", () => setPassword(SERVICE, "account", "password"));
+await bench("getPassword", () => getPassword(SERVICE, "account"));
+await bench("findCredentials", () => findCredentials(SERVICE));
+
+await deletePassword(SERVICE, "account");
+for (let i = 0; i < 10; i++) {
+  await deletePassword(SERVICE, `account${i}`);
+}
@@ -7,8 +7,234 @@ export interface Credential {
   account: string
   password: string
 }
-export function deletePassword(service: string, account: string): Promise<boolean>
-export function findCredentials(service: string): Promise<Array<Credential>>
-export function findPassword(service: string): Promise<string | null>
-export function getPassword(service: string, account: string): Promise<string | null>
-export function setPassword(service: string, account: string, password: string): Promise<void>
+/** A credential found by `searchCredentials`, along with its tags. */
+export interface TaggedCredential {
+  service: string
+  account: string
+  password: string
+  tags: Record<string, string>
+}
+/** A credential found by `findByAccount`. */
+export interface AccountCredential {
+  service: string
+  account: string
+  /** Only set if secrets were requested. */
+  password?: string
+}
+/** A previous password of a credential. */
+export interface PasswordVersion {
+  /** Version of the password, where `1` is the most recent. */
+  version: number
+  password: string
+}
+/** Identifies a credential by its service and account names, which may contain any character. */
+export interface CredentialId {
+  service: string
+  account: string
+}
+export interface KeytarOptions {
+  /**
+   * Whether the keyring may prompt the user to unlock it.
+   * `false` fails fast with an `ERR_KEYTAR_LOCKED` error, `true` shows the unlock prompt.
+   */
+  allowPrompt?: boolean
+  /** Aborts the operation, rejecting with an `AbortError`. */
+  signal?: AbortSignal
+  /**
+   * Time in milliseconds after which the operation is cancelled, rejecting with an `ETIMEDOUT` error.
+   * `0` disables the timeout.
+   * Only libsecret interrupts the operation; on other backends it runs to completion in the background.
+   */
+  timeout?: number
+}
+export interface SetPasswordOptions {
+  /** Same as `KeytarOptions.allowPrompt`. */
+  allowPrompt?: boolean
+  /** Same as `KeytarOptions.signal`. */
+  signal?: AbortSignal
+  /** Same as `KeytarOptions.timeout`. */
+  timeout?: number
+  /** Only stores the password if none is stored yet. */
+  ifAbsent?: boolean
+  /** Only replaces the stored password if it equals this value, compared in constant time. */
+  ifMatch?: string
+  /** Time in milliseconds since the Unix epoch after which the credential is treated as absent. */
+  expiresAt?: number
+  /** Time in milliseconds after which the credential is treated as absent. */
+  ttl?: number
+  /** Tags to store with the credential, e.g. `{ env: 'prod' }`. Not supported on macOS. */
+  tags?: Record<string, string>
+}
+export interface CredentialQuery {
+  /** Attributes that the credentials must have, e.g. `{ service: 'my-app' }`. */
+  attributes?: Record<string, string>
+  /** Tags that the credentials must have, e.g. `{ env: 'prod' }`. */
+  tags?: Record<string, string>
+}
+export interface FindCredentialsOptions {
+  /** Same as `KeytarOptions.allowPrompt`. */
+  allowPrompt?: boolean
+  /** Same as `KeytarOptions.signal`. */
+  signal?: AbortSignal
+  /** Same as `KeytarOptions.timeout`. */
+  timeout?: number
+  /** Maximum number of credentials to return. */
+  limit?: number
+  /** Number of credentials to skip. */
+  offset?: number
+  /** Sorts the credentials in ascending order by account name, creation time or modification time. */
+  sort?: 'account' | 'created' | 'modified'
+  /** Returns a single credential per account, choosing the most recently modified. */
+  dedupe?: boolean
+}
+export interface FindByAccountOptions {
+  /** Same as `KeytarOptions.allowPrompt`. */
+  allowPrompt?: boolean
+  /** Same as `KeytarOptions.signal`. */
+  signal?: AbortSignal
+  /** Same as `KeytarOptions.timeout`. */
+  timeout?: number
+  /** Finds all accounts starting with the given name, rather than only the account itself. */
+  prefix?: boolean
+  /** Includes the password of each credential. */
+  secrets?: boolean
+}
+export interface KeytarConfig {
+  /** Default for `KeytarOptions.allowPrompt`. */
+  allowPrompt?: boolean
+  /** Default for `KeytarOptions.timeout`. */
+  timeout?: number
+  /** Number of threads running keyring operations. Defaults to 2. */
+  concurrency?: number
+  /** How service and account names are normalized. By default, names are used as given. */
+  identifiers?: IdentifierPolicy
+  /** Number of previous passwords kept per credential. Defaults to 0, which keeps none. */
+  history?: number
+  /** Caches the passwords read by `getPassword`. By default, every read goes to the credential store. */
+  cache?: CachePolicy
+}
+export interface CachePolicy {
+  /** Time in milliseconds for which a password is served from the cache. `0` disables the cache. */
+  ttl: number
+  /** Maximum number of cached passwords. Defaults to 256. */
+  maxEntries?: number
+}
+/** Counters of the read cache, as returned by `getCacheStats`. */
+export interface CacheStats {
+  /** Number of reads served from the cache. */
+  hits: number
+  /** Number of reads that went to the credential store while the cache was enabled. */
+  misses: number
+  /** Number of cached passwords. */
+  size: number
+}
+export interface IdentifierPolicy {
+  /** Converts names to Unicode Normalization Form C. */
+  normalize?: boolean
+  /** Converts names to lowercase, so that names differing only in case refer to the same credential. */
+  caseInsensitive?: boolean
+  /** Removes leading and trailing whitespace from names. */
+  trim?: boolean
+}
+export interface CredentialIteratorOptions {
+  /** Number of credentials fetched from the keyring at a time. Defaults to 100. */
+  pageSize?: number
+  /** Same as `KeytarOptions.allowPrompt`. */
+  allowPrompt?: boolean
+  /** Same as `KeytarOptions.timeout`, applied to fetching each page. */
+  timeout?: number
+  /** Sorts the credentials in ascending order by account name, creation time or modification time. */
+  sort?: 'account' | 'created' | 'modified'
+  /** Returns a single credential per account, choosing the most recently modified. */
+  dedupe?: boolean
+  /** Aborts the iteration: pending and later calls to `next` reject with an `AbortError`. */
+  signal?: AbortSignal
+}
+export interface KeyringOptions {
+  /** Service that all credentials of the keyring are stored under. */
+  service: string
+  /**
+   * Credential store to use: `libsecret`, `keychain` or `credential-manager`.
+   * Defaults to the store of the current platform; other stores are rejected.
+   */
+  backend?: string
+  /**
+   * Alias of the collection that credentials are stored in, read from and deleted from (libsecret only).
+   * When unset, new credentials are stored in the `default` collection and all collections are searched.
+   */
+  collection?: string
+  /**
+   * Label of new credentials, where `{service}` and `{account}` are replaced with their names (libsecret only).
+   * Defaults to `{service}/{account}`.
+   */
+  labelTemplate?: string
+  /** Default for `KeytarOptions.allowPrompt` in calls on this keyring. */
+  allowPrompt?: boolean
+  /** Default for `KeytarOptions.timeout` in calls on this keyring. */
+  timeout?: number
+}
+/** A change made to a credential, passed to the callback of `watch`. */
+export interface CredentialChange {
+  type: 'created' | 'changed' | 'deleted'
+  service: string
+  account: string
+}
+export function configure(config: KeytarConfig): void
+export function deletePassword(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<boolean>
+export function findByAccount(account: string, options?: FindByAccountOptions | undefined | null): Promise<Array<AccountCredential>>
+export function findCredentials(service: string, options?: FindCredentialsOptions | undefined | null): Promise<Array<Credential> & { warnings: Array<string> }>
+export function findPassword(service: string | CredentialId, options?: KeytarOptions | undefined | null): Promise<string | null>
+export function getCacheStats(): CacheStats
+export function getJSON(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<any>
+export function getPassword(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<string | null>
+export function getPasswordHistory(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<Array<PasswordVersion>>
+export function getSecretHandle(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<SecretHandle | null>
+export function iterateCredentials(service: string, options?: CredentialIteratorOptions | undefined | null): AsyncIterableIterator<Credential>
+export function patchJSON(service: string, account: string, partial: object, options?: KeytarOptions | undefined | null): Promise<any>
+export function purgeExpired(service?: string | undefined | null, options?: KeytarOptions | undefined | null): Promise<number>
+export function rollbackPassword(service: string, account: string, version: number, options?: KeytarOptions | undefined | null): Promise<boolean>
+export function rotatePassword(service: string, account: string, callback: (oldPassword: string | null) => string | Promise<string>, options?: KeytarOptions | undefined | null): Promise<void>
+export function searchCredentials(query: CredentialQuery, options?: KeytarOptions | undefined | null): Promise<Array<TaggedCredential>>
+export function setJSON(service: string, account: string, value: any, options?: KeytarOptions | undefined | null): Promise<void>
+export function setPassword(service: string, account: string, password: string, options?: SetPasswordOptions | undefined | null): Promise<boolean>
+export function swapPassword(service: string, account: string, password: string, options?: KeytarOptions | undefined | null): Promise<string | null>
+export function takePassword(service: string, account: string, options?: KeytarOptions | undefined | null): Promise<string | null>
+export function verifyPassword(service: string, account: string, candidate: string, options?: KeytarOptions | undefined | null): Promise<boolean>
+export function watch(service: string | undefined | null | ((change: CredentialChange) => void), callback?: (change: CredentialChange) => void, options?: KeytarOptions | undefined | null): Promise<() => void>
+/** Credentials of a single service, accessed with the same settings for every call. */
+export class Keyring {
+  constructor(options: KeyringOptions)
+  /** Service that all credentials of the keyring are stored under. */
+  get service(): string
+  /** Gets the password of the given account. */
+  get(account: string, options?: KeytarOptions | undefined | null): Promise<string | null>
+  /** Stores the password of the given account. */
+  set(account: string, password: string, options?: SetPasswordOptions | undefined | null): Promise<boolean>
+  /** Deletes the password of the given account. */
+  delete(account: string, options?: KeytarOptions | undefined | null): Promise<boolean>
+  /** Finds all credentials of the keyring. */
+  find(options?: FindCredentialsOptions | undefined | null): Promise<Array<Credential> & { warnings: Array<string> }>
+}
+/** Async iterator over the credentials of a service, which fetches them from the keyring one page at a time. */
+export class CredentialIterator {
+  /** Returns the next credential, fetching the next page from the keyring once the current one is used up. */
+  next(): Promise<IteratorResult<Credential, undefined>>
+  /** Stops the iteration, e.g. when breaking out of a `for await...of` loop. */
+  return(): Promise<IteratorResult<Credential, undefined>>
+}
+/**
+ * Opaque handle to a password, which keeps it out of JavaScript strings until it is revealed.
+ * The password is wiped from memory once the handle is disposed or garbage collected.
+ */
+export class SecretHandle {
+  /** Returns the password as a string. The string lives until it is garbage collected. */
+  reveal(): string
+  /** Returns the password as a UTF-8 encoded `Buffer`, which can be wiped with `buffer.fill(0)` once used. */
+  toBuffer(): Buffer
+  /** Whether the password equals the given one, compared in constant time. */
+  equals(other: SecretHandle | string | Buffer): boolean
+  /** Wipes the password from memory. Any later use of the handle throws. */
+  dispose(): void
+  /** Returns a placeholder that never contains the password. */
+  toString(): string
+}
@@ -252,10 +252,30 @@ if (!nativeBinding) {
   throw new Error(`Failed to load native binding`)
 }
 
-const { deletePassword, findCredentials, findPassword, getPassword, setPassword } = nativeBinding
+const { CredentialIterator, Keyring, SecretHandle, configure, deletePassword, findByAccount, findCredentials, findPassword, getCacheStats, getJSON, getPassword, getPasswordHistory, getSecretHandle, iterateCredentials, patchJSON, purgeExpired, rollbackPassword, rotatePassword, searchCredentials, setJSON, setPassword, swapPassword, takePassword, verifyPassword, watch } = nativeBinding
 
+module.exports.CredentialIterator = CredentialIterator
+module.exports.Keyring = Keyring
+module.exports.SecretHandle = SecretHandle
+module.exports.configure = configure
 module.exports.deletePassword = deletePassword
+module.exports.findByAccount = findByAccount
 module.exports.findCredentials = findCredentials
 module.exports.findPassword = findPassword
+module.exports.getCacheStats = getCacheStats
+module.exports.getJSON = getJSON
 module.exports.getPassword = getPassword
+module.exports.getPasswordHistory = getPasswordHistory
+module.exports.getSecretHandle = getSecretHandle
+module.exports.iterateCredentials = iterateCredentials
+module.exports.patchJSON = patchJSON
+module.exports.purgeExpired = purgeExpired
+module.exports.rollbackPassword = rollbackPassword
+module.exports.rotatePassword = rotatePassword
+module.exports.searchCredentials = searchCredentials
+module.exports.setJSON = setJSON
 module.exports.setPassword = setPassword
+module.exports.swapPassword = swapPassword
+module.exports.takePassword = takePassword
+module.exports.verifyPassword = verifyPassword
+module.exports.watch = watch
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+use napi::{Env, Error, JsDeferred, JsObject, Result, Status, Task};
+
+use crate::keytar::cancel::CancelToken;
+use crate::workers::{to_js_error, unlink_signal};
+
+/// Number of threads running keyring operations, unless configured otherwise.
+const DEFAULT_CONCURRENCY: usize = 2;
+
+/// Settles the promise of a task on the JavaScript thread.
+type Resolver<T> = Box<dyn FnOnce(Env) -> Result<<T as Task>::JsValue>>;
+
+/// Deferred promise of a task, taken by whichever settles it first: the task or its cancellation.
+type SharedDeferred<T> = Arc<Mutex<Option<JsDeferred<<T as Task>::JsValue, Resolver<T>>>>>;
+
+struct Job {
+  id: u64,
+  run: Box<dyn FnOnce() + Send>,
+  cancel: Option<CancelToken>,
+}
+
+struct State {
+  jobs: VecDeque<Job>,
+  /// Cancel tokens of the jobs that are currently running, by job ID
+  running: Vec<(u64, CancelToken)>,
+  next_id: u64,
+  concurrency: usize,
+  /// Number of worker threads that are alive
+  workers: usize,
+  /// Node environments (by raw pointer) that have scheduled jobs and are not torn down yet
+  envs: Vec<usize>,
+  /// Bumped on teardown, so that workers started before it exit instead of taking new jobs
+  generation: u64,
+}
+
+/// Runs keyring operations on threads owned by the addon, so that slow keyring calls
+/// do not occupy the libuv threadpool used for `fs` and `dns` work.
+struct Executor {
+  state: Mutex<State>,
+  available: Condvar,
+}
+
+static EXECUTOR: Executor = Executor {
+  state: Mutex::new(State {
+    jobs: VecDeque::new(),
+    running: Vec::new(),
+    next_id: 0,
+    concurrency: DEFAULT_CONCURRENCY,
+    workers: 0,
+    envs: Vec::new(),
+    generation: 0,
+  }),
+  available: Condvar::new(),
+};
+
+fn lock_state() -> MutexGuard<'static, State> {
+  EXECUTOR.state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/**
+ * Helper function to take the deferred promise of a task, unless it was settled already.
+ */
+fn take_deferred<T: Task>(
+  deferred: &SharedDeferred<T>,
+) -> Option<JsDeferred<T::JsValue, Resolver<T>>> {
+  deferred.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/**
+ * Queues a task on the executor.
+ * The promise rejects as soon as the task is cancelled, even if the backend call it is blocked in
+ * cannot be interrupted; the result of such a call is dropped once it returns.
+ * Once the promise settles, the abort listener linked to the cancel token is removed.
+ * Returns: A promise that settles with the result of the task once it has run.
+ */
+pub fn spawn<T: Task + 'static>(
+  mut env: Env,
+  mut task: T,
+  cancel: Option<CancelToken>,
+) -> Result<JsObject>
+where
+  T::JsValue: 'static,
+{
+  register_env(&mut env)?;
+  let (deferred, promise) = env.create_deferred::<T::JsValue, Resolver<T>>()?;
+  let deferred: SharedDeferred<T> = Arc::new(Mutex::new(Some(deferred)));
+  if let Some(cancel) = &cancel {
+    let deferred = deferred.clone();
+    let settled = cancel.clone();
+    cancel.on_cancel(move |reason| {
+      if let Some(deferred) = take_deferred::<T>(&deferred) {
+        deferred.resolve(Box::new(move |env| {
+          let _ = unlink_signal(env, &settled);
+          Err(to_js_error(env, reason))
+        }));
+      }
+    });
+  }
+  let settled = cancel.clone();
+
+  let run = Box::new(move || {
+    // A panic must not unwind into the worker thread, or its promise would never settle
+    let output = panic::catch_unwind(AssertUnwindSafe(|| task.compute())).unwrap_or_else(|panic| {
+      let details = panic
+        .downcast_ref::<&str>()
+        .map(|details| details.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+      Err(Error::new(
+        Status::GenericFailure,
+        format!("[keytar-rs] Keyring operation panicked: {}", details),
+      ))
+    });
+    if let Some(deferred) = take_deferred::<T>(&deferred) {
+      deferred.resolve(Box::new(move |env| {
+        // A listener that cannot be removed must not fail an operation that has completed
+        if let Some(settled) = &settled {
+          let _ = unlink_signal(env, settled);
+        }
+        match output {
+          Ok(output) => task.resolve(env, output),
+          Err(err) => task.reject(env, err),
+        }
+      }));
+    }
+  });
+
+  let mut state = lock_state();
+  let id = state.next_id;
+  state.next_id += 1;
+  state.jobs.push_back(Job { id, run, cancel });
+  if state.workers < state.concurrency {
+    start_worker(&mut state);
+  }
+  drop(state);
+
+  EXECUTOR.available.notify_one();
+  Ok(promise)
+}
+
+/**
+ * Sets the number of threads running keyring operations.
+ * Extra threads exit once their current operation completes.
+ */
+pub fn set_concurrency(concurrency: usize) {
+  let mut state = lock_state();
+  state.concurrency = concurrency.max(1);
+  while state.workers < state.concurrency && state.workers < state.jobs.len() {
+    start_worker(&mut state);
+  }
+  drop(state);
+
+  EXECUTOR.available.notify_all();
+}
+
+fn start_worker(state: &mut State) {
+  state.workers += 1;
+  let generation = state.generation;
+  thread::Builder::new()
+    .name("keytar-worker".to_owned())
+    .spawn(move || run_worker(generation))
+    .expect("failed to spawn keytar worker thread");
+}
+
+fn run_worker(generation: u64) {
+  let mut state = lock_state();
+  loop {
+    if state.generation != generation {
+      // Already uncounted by the teardown that detached this worker
+      return;
+    }
+    if state.workers > state.concurrency {
+      state.workers -= 1;
+      return;
+    }
+
+    let job = match state.jobs.pop_front() {
+      Some(job) => job,
+      None => {
+        state = EXECUTOR
+          .available
+          .wait(state)
+          .unwrap_or_else(|e| e.into_inner());
+        continue;
+      }
+    };
+
+    if let Some(cancel) = job.cancel {
+      state.running.push((job.id, cancel));
+    }
+    drop(state);
+
+    (job.run)();
+
+    state = lock_state();
+    state.running.retain(|(id, _)| *id != job.id);
+  }
+}
+
+/**
+ * Helper function to shut the executor down when the last Node environment using it is torn down.
+ */
+fn register_env(env: &mut Env) -> Result<()> {
+  let raw_env = env.raw() as usize;
+  {
+    let mut state = lock_state();
+    if state.envs.contains(&raw_env) {
+      return Ok(());
+    }
+    state.envs.push(raw_env);
+  }
+
+  env.add_env_cleanup_hook(raw_env, release_env)?;
+  Ok(())
+}
+
+fn release_env(raw_env: usize) {
+  let mut state = lock_state();
+  state.envs.retain(|env| *env != raw_env);
+  if !state.envs.is_empty() {
+    return;
+  }
+
+  // Queued jobs are dropped and running jobs are cancelled. Workers are detached rather than
+  // joined: a keyring call on macOS or Windows cannot be interrupted, and waiting for it would
+  // hold up the teardown of Node.
+  state.generation += 1;
+  state.workers = 0;
+  state.jobs.clear();
+  let running = std::mem::take(&mut state.running);
+  drop(state);
+
+  EXECUTOR.available.notify_all();
+  for (_, cancel) in running {
+    cancel.cancel();
+  }
+}
@@ -0,0 +1,158 @@
+use napi::bindgen_prelude::{Buffer, ClassInstance, Either3, FromNapiValue};
+use napi::{
+  Env, Error, JsBuffer, JsFunction, JsObject, JsString, JsUnknown, NapiRaw, NapiValue, Result,
+  Status, Task,
+};
+use napi_derive::napi;
+use zeroize::Zeroizing;
+
+use crate::keytar;
+use crate::keytar::cache;
+use crate::keytar::condition::secrets_equal;
+use crate::keytar::error::KeytarError;
+use crate::keytar::options::Options;
+use crate::keytar::secret::Secret;
+use crate::workers::{to_js_error, with_legacy_names};
+
+pub struct GetSecretHandle {
+  pub service: String,
+  pub account: String,
+  pub options: Options,
+}
+
+/// Opaque handle to a password, which keeps it out of JavaScript strings until it is revealed.
+/// The password is wiped from memory once the handle is disposed or garbage collected.
+#[napi]
+pub struct SecretHandle {
+  /// Unset once the handle is disposed
+  secret: Option<Secret>,
+}
+
+impl SecretHandle {
+  /**
+   * Helper function to access the password of a handle that was not disposed yet.
+   */
+  fn secret(&self) -> Result<&Secret> {
+    self.secret.as_ref().ok_or_else(|| {
+      Error::new(
+        Status::GenericFailure,
+        "The secret handle was disposed".to_owned(),
+      )
+    })
+  }
+
+  /**
+   * Wraps the handle in a JS object whose `util.inspect` output is redacted like `toString`.
+   * Returns: The handle, with a `Symbol.for('nodejs.util.inspect.custom')` method.
+   */
+  pub fn into_inspectable(self, env: Env) -> Result<JsObject> {
+    let instance = self.into_instance(env)?;
+    let mut handle = unsafe { JsObject::from_raw_unchecked(env.raw(), instance.raw()) };
+
+    let symbol = env
+      .get_global()?
+      .get_named_property::<JsFunction>("Symbol")?
+      .coerce_to_object()?;
+    let symbol_for: JsFunction = symbol.get_named_property("for")?;
+    let inspect_symbol = symbol_for.call(
+      Some(&symbol),
+      &[env.create_string("nodejs.util.inspect.custom")?],
+    )?;
+    let inspect = env.create_function_from_closure("[nodejs.util.inspect.custom]", |ctx| {
+      // Instances of #[napi] classes wrap the struct itself, so they are unwrapped as a class instance
+      let this = ctx.this::<JsObject>()?;
+      let handle =
+        unsafe { ClassInstance::<SecretHandle>::from_napi_value(ctx.env.raw(), this.raw())? };
+      ctx.env.create_string(&handle.describe())
+    })?;
+    handle.set_property(inspect_symbol, inspect)?;
+
+    Ok(handle)
+  }
+}
+
+#[napi]
+impl SecretHandle {
+  /// Returns the password as a string. The string lives until it is garbage collected.
+  #[napi]
+  pub fn reveal(&self, env: Env) -> Result<JsString> {
+    env.create_string(self.secret()?)
+  }
+
+  /// Returns the password as a UTF-8 encoded `Buffer`, which can be wiped with `buffer.fill(0)` once used.
+  #[napi]
+  pub fn to_buffer(&self, env: Env) -> Result<JsBuffer> {
+    Ok(
+      env
+        .create_buffer_copy(self.secret()?.as_bytes())?
+        .into_raw(),
+    )
+  }
+
+  /// Whether the password equals the given one, compared in constant time.
+  #[napi]
+  pub fn equals(
+    &self,
+    other: Either3<ClassInstance<SecretHandle>, String, Buffer>,
+  ) -> Result<bool> {
+    let secret = self.secret()?;
+    Ok(match other {
+      Either3::A(handle) => secrets_equal(secret, handle.secret()?),
+      Either3::B(candidate) => secrets_equal(secret, &Zeroizing::new(candidate)),
+      Either3::C(candidate) => match std::str::from_utf8(&candidate) {
+        Ok(candidate) => secrets_equal(secret, candidate),
+        Err(_) => false,
+      },
+    })
+  }
+
+  /// Wipes the password from memory. Any later use of the handle throws.
+  #[napi]
+  pub fn dispose(&mut self) {
+    self.secret = None;
+  }
+
+  /// Returns a placeholder that never contains the password.
+  #[napi(js_name = "toString")]
+  pub fn describe(&self) -> String {
+    match self.secret {
+      Some(_) => "SecretHandle <redacted>".to_owned(),
+      None => "SecretHandle <disposed>".to_owned(),
+    }
+  }
+}
+
+#[napi]
+impl Task for GetSecretHandle {
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      cache::get_password(&self.service, &self.account, &self.options, || {
+        with_legacy_names(
+          &self.service,
+          &self.account,
+          &self.options,
+          |service, account| keytar::get_credential(service, account, &self.options),
+        )
+      })
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(match output {
+      Ok(Some(secret)) => SecretHandle {
+        secret: Some(secret),
+      }
+      .into_inspectable(env)?
+      .into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
+    })
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use napi::{Env, Error, JsFunction, JsObject, JsUnknown, NapiRaw, NapiValue, Result, Task};
+use napi_derive::napi;
+
+use crate::executor;
+use crate::keytar;
+use crate::keytar::cancel::CancelToken;
+use crate::keytar::error::KeytarError;
+use crate::keytar::options::Options;
+use crate::keytar::search::{Page, Search};
+use crate::keytar::secret::Secret;
+use crate::workers::{link_signal, service_names, to_js_error, to_sort_key, unlink_signal};
+
+/// Number of credentials fetched per page, unless configured otherwise.
+const DEFAULT_PAGE_SIZE: u32 = 100;
+
+#[napi(object)]
+pub struct CredentialIteratorOptions {
+  /// Number of credentials fetched from the keyring at a time. Defaults to 100.
+  pub page_size: Option<u32>,
+  /// Same as `KeytarOptions.allowPrompt`.
+  pub allow_prompt: Option<bool>,
+  /// Same as `KeytarOptions.timeout`, applied to fetching each page.
+  pub timeout: Option<u32>,
+  /// Sorts the credentials in ascending order by account name, creation time or modification time.
+  #[napi(ts_type = "'account' | 'created' | 'modified'")]
+  pub sort: Option<String>,
+  /// Returns a single credential per account, choosing the most recently modified.
+  pub dedupe: Option<bool>,
+  /// Aborts the iteration: pending and later calls to `next` reject with an `AbortError`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+}
+
+/// Credentials fetched by an iterator that have not been returned yet.
+#[derive(Default)]
+struct Pages {
+  buffer: VecDeque<(String, Secret)>,
+  /// Offset of the next page to fetch
+  offset: usize,
+  done: bool,
+}
+
+/// Async iterator over the credentials of a service, which fetches them from the keyring one page at a time.
+#[napi]
+pub struct CredentialIterator {
+  service: String,
+  /// Sort order and deduplication of the iteration; its page is set for each fetch
+  search: Search,
+  page_size: usize,
+  allow_prompt: Option<bool>,
+  timeout: Option<u32>,
+  /// Cancelled when the signal of the iteration aborts
+  cancel: CancelToken,
+  pages: Arc<Mutex<Pages>>,
+}
+
+pub struct NextCredential {
+  pub service: String,
+  pub search: Search,
+  pub page_size: usize,
+  pages: Arc<Mutex<Pages>>,
+  pub options: Options,
+}
+
+impl CredentialIterator {
+  pub fn new(
+    env: Env,
+    service: String,
+    options: Option<CredentialIteratorOptions>,
+  ) -> Result<Self> {
+    let options = options.unwrap_or(CredentialIteratorOptions {
+      page_size: None,
+      allow_prompt: None,
+      timeout: None,
+      sort: None,
+      dedupe: None,
+      signal: None,
+    });
+    let cancel = CancelToken::new();
+    if let Some(signal) = options.signal {
+      link_signal(env, signal, &cancel)?;
+    }
+
+    Ok(CredentialIterator {
+      service,
+      search: Search {
+        sort: to_sort_key(options.sort)?,
+        dedupe: options.dedupe.unwrap_or(false),
+        page: Page::default(),
+      },
+      page_size: options.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1) as usize,
+      allow_prompt: options.allow_prompt,
+      timeout: options.timeout,
+      cancel,
+      pages: Arc::new(Mutex::new(Pages::default())),
+    })
+  }
+
+  /**
+   * Wraps the iterator in a JS object that can be used with `for await...of`.
+   * Returns: The iterator, with a `Symbol.asyncIterator` method that returns itself.
+   */
+  pub fn into_async_iterable(self, env: Env) -> Result<JsObject> {
+    let instance = self.into_instance(env)?;
+    let mut iterator = unsafe { JsObject::from_raw_unchecked(env.raw(), instance.raw()) };
+
+    let symbol: JsFunction = env.get_global()?.get_named_property("Symbol")?;
+    let async_iterator_symbol: JsUnknown = symbol
+      .coerce_to_object()?
+      .get_named_property("asyncIterator")?;
+    let async_iterator =
+      env.create_function_from_closure("[Symbol.asyncIterator]", |ctx| ctx.this::<JsObject>())?;
+    iterator.set_property(async_iterator_symbol, async_iterator)?;
+
+    Ok(iterator)
+  }
+}
+
+#[napi]
+impl CredentialIterator {
+  /// Returns the next credential, fetching the next page from the keyring once the current one is used up.
+  #[napi(ts_return_type = "Promise<IteratorResult<Credential, undefined>>")]
+  pub fn next(&self, env: Env) -> Result<JsObject> {
+    let options = Options {
+      allow_prompt: self.allow_prompt,
+      // Each call has its own token for its timeout, which the signal of the iteration cancels as well
+      cancel: Some(self.cancel.child()),
+      timeout: self.timeout,
+      ..Options::default()
+    }
+    .with_defaults()
+    .start_timeout();
+    let cancel = options.cancel.clone();
+
+    executor::spawn(
+      env,
+      NextCredential {
+        service: self.service.clone(),
+        search: self.search,
+        page_size: self.page_size,
+        pages: self.pages.clone(),
+        options,
+      },
+      cancel,
+    )
+  }
+
+  /// Stops the iteration, e.g. when breaking out of a `for await...of` loop.
+  #[napi(
+    js_name = "return",
+    ts_return_type = "Promise<IteratorResult<Credential, undefined>>"
+  )]
+  pub fn finish(&self, env: Env) -> Result<JsObject> {
+    {
+      let mut pages = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+      pages.buffer.clear();
+      pages.done = true;
+    }
+    unlink_signal(env, &self.cancel)?;
+
+    let (deferred, promise) = env.create_deferred()?;
+    deferred.resolve(|env| to_iterator_result(env, None));
+    Ok(promise)
+  }
+}
+
+/**
+ * Helper function to build the result of an iterator step.
+ * Returns: An object in the form `{ done, value }`.
+ */
+fn to_iterator_result(env: Env, credential: Option<(String, Secret)>) -> Result<JsObject> {
+  let mut result = env.create_object()?;
+  match credential {
+    Some((account, password)) => {
+      result.set_named_property("done", false)?;
+      let mut value = env.create_object()?;
+      value.set_named_property("account", env.create_string(&account)?)?;
+      value.set_named_property("password", env.create_string(&password)?)?;
+      result.set_named_property("value", value)?;
+    }
+    None => {
+      result.set_named_property("done", true)?;
+      result.set_named_property("value", env.get_undefined()?)?;
+    }
+  }
+
+  Ok(result)
+}
+
+#[napi]
+impl Task for NextCredential {
+  type Output = std::result::Result<Option<(String, Secret)>, KeytarError>;
+  type JsValue = JsObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      // The lock is held while fetching, so that concurrent calls do not fetch the same page
+      let mut pages = self.pages.lock().unwrap_or_else(|e| e.into_inner());
+      // Pages may come back empty if all of their items were skipped
+      while pages.buffer.is_empty() && !pages.done {
+        let search = Search {
+          page: Page {
+            offset: pages.offset,
+            limit: Some(self.page_size),
+          },
+          ..self.search
+        };
+        let mut credentials: Vec<(String, Secret)> = Vec::new();
+        let mut warnings: Vec<String> = Vec::new();
+        let more = keytar::find_credentials(
+          &service_names(&self.service),
+          &mut credentials,
+          &mut warnings,
+          &search,
+          &self.options,
+        )?;
+
+        // Skipped items still take up their place in the page
+        pages.offset += self.page_size;
+        pages.done = !more;
+        pages.buffer.extend(credentials);
+      }
+
+      Ok(pages.buffer.pop_front())
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(credential) => to_iterator_result(env, credential),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
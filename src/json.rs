@@ -0,0 +1,260 @@
+use std::io;
+
+use napi::{Env, Error, JsUnknown, Result, Task};
+use napi_derive::napi;
+use serde_json::{Map, Value};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::keytar;
+use crate::keytar::error::KeytarError;
+use crate::keytar::history;
+use crate::keytar::identifier;
+use crate::keytar::lock;
+use crate::keytar::options::Options;
+use crate::keytar::secret::{self, Secret};
+use crate::workers::{to_js_error, with_legacy_names};
+
+/// Content type recorded with credentials that hold a JSON value.
+const CONTENT_TYPE_JSON: &str = "application/json";
+
+pub struct SetJSON {
+  pub service: String,
+  pub account: String,
+  /// The value, already serialized with `to_secret`
+  pub value: Secret,
+  pub options: Options,
+}
+
+pub struct GetJSON {
+  pub service: String,
+  pub account: String,
+  pub options: Options,
+}
+
+pub struct PatchJSON {
+  pub service: String,
+  pub account: String,
+  pub partial: SecretValue,
+  pub options: Options,
+}
+
+/// JSON value that may hold secrets, whose strings are wiped from memory once it is dropped.
+/// Numbers, booleans and the structure of the value are not wiped.
+pub struct SecretValue(pub Value);
+
+impl Drop for SecretValue {
+  fn drop(&mut self) {
+    wipe(&mut self.0);
+  }
+}
+
+/// Counts the bytes written to it, to size the buffer of a serialized value up front.
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0 += buf.len();
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/**
+ * Helper function to wipe the strings and object keys of a JSON value from memory.
+ * The value is left with empty strings and objects.
+ */
+fn wipe(value: &mut Value) {
+  match value {
+    Value::String(string) => string.zeroize(),
+    Value::Array(values) => values.iter_mut().for_each(wipe),
+    Value::Object(map) => {
+      for (mut key, mut value) in std::mem::take(map) {
+        key.zeroize();
+        wipe(&mut value);
+      }
+    }
+    _ => {}
+  }
+}
+
+/**
+ * Serializes a JSON value into a buffer that is wiped once dropped.
+ * The size is computed first, so the buffer is never reallocated, which would leave copies behind.
+ */
+fn serialize(value: &Value) -> std::result::Result<Secret, KeytarError> {
+  let mut counter = ByteCounter(0);
+  serde_json::to_writer(&mut counter, value)?;
+  let mut bytes = Zeroizing::new(Vec::with_capacity(counter.0));
+  serde_json::to_writer(&mut *bytes, value)?;
+  secret::from_utf8(bytes)
+}
+
+/**
+ * Serializes a JSON value passed from JavaScript as the password of a credential,
+ * and wipes the value, so it is only kept in serialized form.
+ */
+pub fn to_secret(value: Value) -> std::result::Result<Secret, KeytarError> {
+  let value = SecretValue(value);
+  serialize(&value.0)
+}
+
+/**
+ * Helper function to read and parse the JSON value of a credential.
+ * Returns:
+ * Some(value) if the credential exists,
+ * or None otherwise.
+ */
+fn read_json(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> std::result::Result<Option<SecretValue>, KeytarError> {
+  match with_legacy_names(service, account, options, |service, account| {
+    keytar::get_password(service, account, options)
+  })? {
+    Some(password) => Ok(Some(SecretValue(serde_json::from_str(&password)?))),
+    None => Ok(None),
+  }
+}
+
+/**
+ * Helper function to store a serialized JSON value as the password of a credential.
+ */
+fn write_json(
+  service: &String,
+  account: &String,
+  password: &mut Secret,
+  options: &Options,
+) -> std::result::Result<bool, KeytarError> {
+  history::write(
+    &identifier::normalize(service).into_owned(),
+    &identifier::normalize(account).into_owned(),
+    password,
+    &Options {
+      content_type: Some(CONTENT_TYPE_JSON.to_owned()),
+      ..options.clone()
+    },
+  )
+}
+
+/**
+ * Helper function to apply a JSON merge patch (RFC 7396) to a value.
+ * Objects are merged recursively, `null` removes a field and any other value replaces the target.
+ */
+fn merge_patch(target: &mut Value, patch: Value) {
+  match patch {
+    Value::Object(patch) => {
+      if !target.is_object() {
+        *target = Value::Object(Map::new());
+      }
+      if let Value::Object(target) = target {
+        for (mut key, value) in patch {
+          if value.is_null() {
+            if let Some(mut removed) = target.remove(&key) {
+              wipe(&mut removed);
+            }
+            key.zeroize();
+          } else {
+            merge_patch(target.entry(key).or_insert(Value::Null), value);
+          }
+        }
+      }
+    }
+    patch => {
+      // The replaced value may hold secrets as well
+      wipe(target);
+      *target = patch;
+    }
+  }
+}
+
+#[napi]
+impl Task for SetJSON {
+  type Output = std::result::Result<bool, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let _lock = lock::lock(
+        &identifier::normalize(&self.service),
+        &identifier::normalize(&self.account),
+      );
+      write_json(&self.service, &self.account, &mut self.value, &self.options)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(_) => Ok(env.get_null()?.into_unknown()),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for GetJSON {
+  type Output = std::result::Result<Option<SecretValue>, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(
+      self
+        .options
+        .run(|| read_json(&self.service, &self.account, &self.options)),
+    )
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(Some(value)) => env.to_js_value(&value.0),
+      Ok(None) => Ok(env.get_null()?.into_unknown()),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for PatchJSON {
+  type Output = std::result::Result<SecretValue, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      // Hold the lock while reading, patching and writing the value,
+      // so no other call of this process changes it in between
+      let _lock = lock::lock(
+        &identifier::normalize(&self.service),
+        &identifier::normalize(&self.account),
+      );
+      // A missing credential is patched like an empty object
+      let mut value =
+        read_json(&self.service, &self.account, &self.options)?.unwrap_or(SecretValue(Value::Null));
+      merge_patch(&mut value.0, self.partial.0.take());
+      let mut password = serialize(&value.0)?;
+      write_json(&self.service, &self.account, &mut password, &self.options)?;
+      Ok(value)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(value) => env.to_js_value(&value.0),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
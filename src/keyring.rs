@@ -0,0 +1,180 @@
+use napi::{Env, Error, JsObject, Result, Status};
+use napi_derive::napi;
+use zeroize::Zeroizing;
+
+use crate::executor;
+use crate::keytar;
+use crate::keytar::options::Options;
+use crate::workers::{
+  to_options, to_search, to_write_options, DeletePassword, FindCredentials, FindCredentialsOptions,
+  GetPassword, KeytarOptions, SetPassword, SetPasswordOptions,
+};
+
+#[napi(object)]
+pub struct KeyringOptions {
+  /// Service that all credentials of the keyring are stored under.
+  pub service: String,
+  /// Credential store to use: `libsecret`, `keychain` or `credential-manager`.
+  /// Defaults to the store of the current platform; other stores are rejected.
+  pub backend: Option<String>,
+  /// Alias of the collection that credentials are stored in, read from and deleted from (libsecret only).
+  /// When unset, new credentials are stored in the `default` collection and all collections are searched.
+  pub collection: Option<String>,
+  /// Label of new credentials, where `{service}` and `{account}` are replaced with their names (libsecret only).
+  /// Defaults to `{service}/{account}`.
+  pub label_template: Option<String>,
+  /// Default for `KeytarOptions.allowPrompt` in calls on this keyring.
+  pub allow_prompt: Option<bool>,
+  /// Default for `KeytarOptions.timeout` in calls on this keyring.
+  pub timeout: Option<u32>,
+}
+
+/// Credentials of a single service, accessed with the same settings for every call.
+#[napi]
+pub struct Keyring {
+  service: String,
+  collection: Option<String>,
+  label_template: Option<String>,
+  allow_prompt: Option<bool>,
+  timeout: Option<u32>,
+}
+
+impl Keyring {
+  /**
+   * Helper function to build the options for a call on this keyring.
+   * Options passed to the call take precedence over the keyring's, which take precedence over `configure`.
+   */
+  fn to_call_options(&self, env: Env, options: Option<KeytarOptions>) -> Result<Options> {
+    let options = options.unwrap_or(KeytarOptions {
+      allow_prompt: None,
+      signal: None,
+      timeout: None,
+    });
+
+    let mut options = to_options(
+      env,
+      Some(KeytarOptions {
+        allow_prompt: options.allow_prompt.or(self.allow_prompt),
+        signal: options.signal,
+        timeout: options.timeout.or(self.timeout),
+      }),
+    )?;
+    options.collection = self.collection.clone();
+    options.label_template = self.label_template.clone();
+    Ok(options)
+  }
+}
+
+#[napi]
+impl Keyring {
+  #[napi(constructor)]
+  pub fn new(options: KeyringOptions) -> Result<Self> {
+    if let Some(backend) = options.backend {
+      if backend != keytar::BACKEND {
+        return Err(Error::new(
+          Status::InvalidArg,
+          format!(
+            "Backend '{}' is not available on this platform, expected '{}'",
+            backend,
+            keytar::BACKEND
+          ),
+        ));
+      }
+    }
+
+    Ok(Keyring {
+      service: options.service,
+      collection: options.collection,
+      label_template: options.label_template,
+      allow_prompt: options.allow_prompt,
+      timeout: options.timeout,
+    })
+  }
+
+  /// Service that all credentials of the keyring are stored under.
+  #[napi(getter)]
+  pub fn service(&self) -> String {
+    self.service.clone()
+  }
+
+  /// Gets the password of the given account.
+  #[napi(ts_return_type = "Promise<string | null>")]
+  pub fn get(&self, env: Env, account: String, options: Option<KeytarOptions>) -> Result<JsObject> {
+    let options = self.to_call_options(env, options)?;
+    let cancel = options.cancel.clone();
+    executor::spawn(
+      env,
+      GetPassword {
+        service: self.service.clone(),
+        account,
+        options,
+      },
+      cancel,
+    )
+  }
+
+  /// Stores the password of the given account.
+  #[napi(ts_return_type = "Promise<boolean>")]
+  pub fn set(
+    &self,
+    env: Env,
+    account: String,
+    password: String,
+    options: Option<SetPasswordOptions>,
+  ) -> Result<JsObject> {
+    let (options, write) = to_write_options(options)?;
+    let mut options = self.to_call_options(env, options)?;
+    options.expires_at = write.expires_at;
+    options.tags = write.tags;
+    let cancel = options.cancel.clone();
+    executor::spawn(
+      env,
+      SetPassword {
+        service: self.service.clone(),
+        account,
+        password: Zeroizing::new(password),
+        condition: write.condition,
+        options,
+      },
+      cancel,
+    )
+  }
+
+  /// Deletes the password of the given account.
+  #[napi(ts_return_type = "Promise<boolean>")]
+  pub fn delete(
+    &self,
+    env: Env,
+    account: String,
+    options: Option<KeytarOptions>,
+  ) -> Result<JsObject> {
+    let options = self.to_call_options(env, options)?;
+    let cancel = options.cancel.clone();
+    executor::spawn(
+      env,
+      DeletePassword {
+        service: self.service.clone(),
+        account,
+        options,
+      },
+      cancel,
+    )
+  }
+
+  /// Finds all credentials of the keyring.
+  #[napi(ts_return_type = "Promise<Array<Credential> & { warnings: Array<string> }>")]
+  pub fn find(&self, env: Env, options: Option<FindCredentialsOptions>) -> Result<JsObject> {
+    let (options, search) = to_search(options)?;
+    let options = self.to_call_options(env, options)?;
+    let cancel = options.cancel.clone();
+    executor::spawn(
+      env,
+      FindCredentials {
+        service: self.service.clone(),
+        search,
+        options,
+      },
+      cancel,
+    )
+  }
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::error::KeytarError;
+use super::identifier;
+use super::options::{self, Options};
+use super::secret::Secret;
+
+/// Settings of the read cache.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+  /// Time after which a cached password is read from the credential store again.
+  pub ttl: Duration,
+  /// Maximum number of cached passwords; the oldest are evicted first.
+  pub max_entries: usize,
+}
+
+/// Identifies a cached credential by its collection, service and account.
+type Key = (Option<String>, String, String);
+
+struct Entry {
+  /// Password read from the credential store, wiped from memory once the entry is dropped.
+  /// Missing credentials are cached too, as None.
+  password: Option<Secret>,
+  /// Expiry time of the credential in milliseconds since the Unix epoch, after which it is read again.
+  expires_at: Option<u64>,
+  cached_at: Instant,
+}
+
+impl Entry {
+  /// Returns whether the entry may still be served: neither the cache TTL nor the credential has expired.
+  fn is_fresh(&self, ttl: Duration) -> bool {
+    self.cached_at.elapsed() < ttl
+      && !matches!(self.expires_at, Some(expires_at) if expires_at <= options::now_millis())
+  }
+}
+
+struct Cache {
+  config: Config,
+  entries: HashMap<Key, Entry>,
+  /// Incremented whenever entries are invalidated, so that a password read before then is not cached.
+  generation: u64,
+}
+
+/// The read cache, which is disabled when unset.
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+fn lock_cache() -> MutexGuard<'static, Option<Cache>> {
+  CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/**
+ * Enables the read cache with the given settings, or disables it. Cached passwords are dropped either way.
+ */
+pub fn configure(config: Option<Config>) {
+  *lock_cache() = config.map(|config| Cache {
+    config,
+    entries: HashMap::new(),
+    generation: 0,
+  });
+}
+
+/**
+ * Reads the current password of a credential from the cache, or loads and caches it on a miss.
+ * `load` returns the password along with its expiry time, after which the cached password is not served.
+ * Previous passwords are never cached.
+ */
+pub fn get_password(
+  service: &str,
+  account: &str,
+  options: &Options,
+  load: impl FnOnce() -> Result<Option<(Secret, Option<u64>)>, KeytarError>,
+) -> Result<Option<Secret>, KeytarError> {
+  if options.version.is_some() {
+    return Ok(load()?.map(|(password, _)| password));
+  }
+
+  let key: Key = (
+    options.collection.clone(),
+    identifier::normalize(service).into_owned(),
+    identifier::normalize(account).into_owned(),
+  );
+  let generation = match lock_cache().as_ref() {
+    Some(cache) => match cache.entries.get(&key) {
+      Some(entry) if entry.is_fresh(cache.config.ttl) => {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(entry.password.clone());
+      }
+      _ => Some(cache.generation),
+    },
+    None => None,
+  };
+  let generation = match generation {
+    Some(generation) => generation,
+    None => return Ok(load()?.map(|(password, _)| password)),
+  };
+
+  // The lock is not held while loading, so that other credentials can be read meanwhile
+  MISSES.fetch_add(1, Ordering::Relaxed);
+  let (password, expires_at) = match load()? {
+    Some((password, expires_at)) => (Some(password), expires_at),
+    None => (None, None),
+  };
+
+  let mut cache = lock_cache();
+  if let Some(cache) = cache.as_mut() {
+    if cache.generation == generation && cache.config.max_entries > 0 {
+      let ttl = cache.config.ttl;
+      cache.entries.retain(|_, entry| entry.is_fresh(ttl));
+      if cache.entries.len() >= cache.config.max_entries && !cache.entries.contains_key(&key) {
+        let oldest = cache
+          .entries
+          .iter()
+          .min_by_key(|(_, entry)| entry.cached_at)
+          .map(|(key, _)| key.clone());
+        if let Some(oldest) = oldest {
+          cache.entries.remove(&oldest);
+        }
+      }
+
+      cache.entries.insert(
+        key,
+        Entry {
+          password: password.clone(),
+          expires_at,
+          cached_at: Instant::now(),
+        },
+      );
+    }
+  }
+
+  Ok(password)
+}
+
+/**
+ * Drops the cached password of a credential in all collections, after it was written or deleted.
+ */
+pub fn invalidate(service: &str, account: &str) {
+  if let Some(cache) = lock_cache().as_mut() {
+    cache.generation += 1;
+    cache
+      .entries
+      .retain(|(_, cached_service, cached_account), _| {
+        (cached_service.as_str(), cached_account.as_str()) != (service, account)
+      });
+  }
+}
+
+/**
+ * Drops all cached passwords, e.g. after credentials of any service were deleted.
+ */
+pub fn clear() {
+  if let Some(cache) = lock_cache().as_mut() {
+    cache.generation += 1;
+    cache.entries.clear();
+  }
+}
+
+/**
+ * Returns: The number of reads served from the cache, the number of reads that missed it,
+ * and the number of cached passwords.
+ */
+pub fn stats() -> (u64, u64, usize) {
+  let size = lock_cache().as_ref().map_or(0, |cache| cache.entries.len());
+  (
+    HITS.load(Ordering::Relaxed),
+    MISSES.load(Ordering::Relaxed),
+    size,
+  )
+}
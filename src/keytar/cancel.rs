@@ -0,0 +1,232 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::error::KeytarError;
+
+type Callback = Box<dyn FnOnce(KeytarError) + Send>;
+
+const ACTIVE: u8 = 0;
+const ABORTED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+
+#[derive(Default)]
+struct Inner {
+  state: AtomicU8,
+  callbacks: Mutex<Vec<Callback>>,
+  /// Tokens cancelled along with this one, dropped once their owner no longer needs them
+  children: Mutex<Vec<Weak<Inner>>>,
+}
+
+/// A cancellation flag shared between JavaScript and the thread running a keyring operation.
+/// Backends register callbacks to abort their native calls once the token is cancelled.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+impl CancelToken {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Marks the token as aborted and runs all registered callbacks.
+  pub fn cancel(&self) {
+    self.cancel_with(ABORTED);
+  }
+
+  /// Creates a token that is cancelled along with this one but can be cancelled on its own,
+  /// without leaving anything registered on this token once it is dropped.
+  pub fn child(&self) -> Self {
+    let child = Self::new();
+    {
+      let mut children = self.0.children.lock().unwrap_or_else(|e| e.into_inner());
+      if !self.is_cancelled() {
+        children.retain(|child| child.strong_count() > 0);
+        children.push(Arc::downgrade(&child.0));
+        return child;
+      }
+    }
+
+    child.cancel_with(self.0.state.load(Ordering::SeqCst));
+    child
+  }
+
+  /// Cancels the token with a timeout error once `timeout` has elapsed.
+  pub fn cancel_after(&self, timeout: Duration) {
+    schedule_timeout(Instant::now() + timeout, Arc::downgrade(&self.0));
+  }
+
+  fn cancel_with(&self, state: u8) {
+    if self
+      .0
+      .state
+      .compare_exchange(ACTIVE, state, Ordering::SeqCst, Ordering::SeqCst)
+      .is_err()
+    {
+      return;
+    }
+
+    let callbacks =
+      std::mem::take(&mut *self.0.callbacks.lock().unwrap_or_else(|e| e.into_inner()));
+    for callback in callbacks {
+      callback(to_error(state));
+    }
+
+    let children = std::mem::take(&mut *self.0.children.lock().unwrap_or_else(|e| e.into_inner()));
+    for child in children {
+      if let Some(inner) = child.upgrade() {
+        CancelToken(inner).cancel_with(state);
+      }
+    }
+  }
+
+  /// Whether both tokens are clones of the same token.
+  pub fn same_as(&self, other: &CancelToken) -> bool {
+    Arc::ptr_eq(&self.0, &other.0)
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.0.state.load(Ordering::SeqCst) != ACTIVE
+  }
+
+  /// Registers a callback to run on cancellation, given the error describing why.
+  /// Runs it right away if the token is already cancelled.
+  pub fn on_cancel<F: FnOnce(KeytarError) + Send + 'static>(&self, callback: F) {
+    {
+      let mut callbacks = self.0.callbacks.lock().unwrap_or_else(|e| e.into_inner());
+      if !self.is_cancelled() {
+        callbacks.push(Box::new(callback));
+        return;
+      }
+    }
+
+    callback(to_error(self.0.state.load(Ordering::SeqCst)));
+  }
+
+  /// Returns an error describing why the token was cancelled, if it has been cancelled.
+  pub fn check(&self) -> Result<(), KeytarError> {
+    match self.0.state.load(Ordering::SeqCst) {
+      ACTIVE => Ok(()),
+      state => Err(to_error(state)),
+    }
+  }
+}
+
+/**
+ * Helper function to build the error reported for a cancelled token, given its state.
+ */
+fn to_error(state: u8) -> KeytarError {
+  match state {
+    TIMED_OUT => KeytarError::TimedOut,
+    _ => KeytarError::Cancelled,
+  }
+}
+
+impl fmt::Debug for CancelToken {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("CancelToken")
+      .field("cancelled", &self.is_cancelled())
+      .finish()
+  }
+}
+
+struct Timeout {
+  deadline: Instant,
+  token: Weak<Inner>,
+}
+
+impl PartialEq for Timeout {
+  fn eq(&self, other: &Self) -> bool {
+    self.deadline == other.deadline
+  }
+}
+
+impl Eq for Timeout {}
+
+impl PartialOrd for Timeout {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Timeout {
+  fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    self.deadline.cmp(&other.deadline)
+  }
+}
+
+struct Timer {
+  timeouts: Mutex<BinaryHeap<Reverse<Timeout>>>,
+  wakeup: Condvar,
+}
+
+static TIMER: Mutex<Option<Arc<Timer>>> = Mutex::new(None);
+
+/**
+ * Helper function to queue a timeout on the shared timer thread, starting the thread on first use.
+ * Tokens that were dropped before their deadline are skipped.
+ */
+fn schedule_timeout(deadline: Instant, token: Weak<Inner>) {
+  let timer = {
+    let mut timer = TIMER.lock().unwrap_or_else(|e| e.into_inner());
+    match timer.as_ref() {
+      Some(timer) => timer.clone(),
+      None => {
+        let new_timer = Arc::new(Timer {
+          timeouts: Mutex::new(BinaryHeap::new()),
+          wakeup: Condvar::new(),
+        });
+        let thread_timer = new_timer.clone();
+        thread::Builder::new()
+          .name("keytar-timer".to_owned())
+          .spawn(move || run_timer(&thread_timer))
+          .expect("failed to spawn keytar timer thread");
+        *timer = Some(new_timer.clone());
+        new_timer
+      }
+    }
+  };
+
+  timer
+    .timeouts
+    .lock()
+    .unwrap_or_else(|e| e.into_inner())
+    .push(Reverse(Timeout { deadline, token }));
+  timer.wakeup.notify_one();
+}
+
+fn run_timer(timer: &Timer) {
+  let mut timeouts = timer.timeouts.lock().unwrap_or_else(|e| e.into_inner());
+  loop {
+    let now = Instant::now();
+    while let Some(Reverse(next)) = timeouts.peek() {
+      if next.deadline > now {
+        break;
+      }
+
+      if let Some(Reverse(expired)) = timeouts.pop() {
+        if let Some(inner) = expired.token.upgrade() {
+          CancelToken(inner).cancel_with(TIMED_OUT);
+        }
+      }
+    }
+
+    timeouts = match timeouts.peek() {
+      Some(Reverse(next)) => {
+        let wait = next.deadline.saturating_duration_since(now);
+        timer
+          .wakeup
+          .wait_timeout(timeouts, wait)
+          .unwrap_or_else(|e| e.into_inner())
+          .0
+      }
+      None => timer
+        .wakeup
+        .wait(timeouts)
+        .unwrap_or_else(|e| e.into_inner()),
+    };
+  }
+}
@@ -0,0 +1,28 @@
+/// Kind of change made to a credential.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+  Created,
+  Changed,
+  Deleted,
+}
+
+impl ChangeKind {
+  /// Returns the value exposed as `type` on the JavaScript event.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ChangeKind::Created => "created",
+      ChangeKind::Changed => "changed",
+      ChangeKind::Deleted => "deleted",
+    }
+  }
+}
+
+/// A change made to a credential by any process, as reported by the credential store.
+pub struct Change {
+  pub kind: ChangeKind,
+  pub service: String,
+  pub account: String,
+}
+
+/// Receives the changes reported to a watch, on a thread owned by the backend.
+pub type ChangeCallback = Box<dyn Fn(Change) + Send>;
@@ -0,0 +1,43 @@
+use std::fmt;
+
+use subtle::ConstantTimeEq;
+
+use super::secret::Secret;
+
+/// Condition under which a password is written, checked against the stored password.
+#[derive(Clone)]
+pub enum WriteCondition {
+  /// Only write if no password is stored.
+  IfAbsent,
+  /// Only write if the stored password equals the given one.
+  IfMatch(Secret),
+}
+
+impl fmt::Debug for WriteCondition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      WriteCondition::IfAbsent => f.write_str("IfAbsent"),
+      // The expected password is left out, so it never ends up in logs or error messages
+      WriteCondition::IfMatch(_) => f.write_str("IfMatch(..)"),
+    }
+  }
+}
+
+impl WriteCondition {
+  /// Whether a write may replace the stored password, given as `None` if there is none.
+  pub fn is_met(&self, current: Option<&str>) -> bool {
+    match (self, current) {
+      (WriteCondition::IfAbsent, current) => current.is_none(),
+      (WriteCondition::IfMatch(expected), Some(current)) => secrets_equal(expected, current),
+      (WriteCondition::IfMatch(_), None) => false,
+    }
+  }
+}
+
+/**
+ * Helper function to compare two secrets in constant time, so the time taken reveals
+ * nothing about how many leading characters match. Only their lengths may leak.
+ */
+pub fn secrets_equal(a: &str, b: &str) -> bool {
+  a.as_bytes().ct_eq(b.as_bytes()).into()
+}
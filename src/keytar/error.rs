@@ -1,5 +1,6 @@
 use std::{str::Utf8Error, string::FromUtf16Error, string::FromUtf8Error};
 use thiserror::Error;
+use zeroize::Zeroize;
 
 #[derive(Error, Debug)]
 pub enum KeytarError {
@@ -9,9 +10,24 @@ pub enum KeytarError {
   #[error("[keytar-rs] {name:?} library returned an error:\n\n{details:?}")]
   Library { name: String, details: String },
 
+  #[error("[keytar-rs] The operation was aborted.")]
+  Cancelled,
+
+  #[error("[keytar-rs] The keyring is locked and interactive prompts are not allowed.")]
+  Locked,
+
   #[error("[keytar-rs] No items were found that match the given parameters.")]
   NotFound,
 
+  #[error("[keytar-rs] The password was changed by another call while it was being rotated.")]
+  Conflict,
+
+  #[error("[keytar-rs] The operation is not supported by this credential store:\n\n{0}")]
+  Unsupported(String),
+
+  #[error("[keytar-rs] The operation did not complete before its timeout.")]
+  TimedOut,
+
   #[error("[keytar-rs] An OS error has occurred:\n\n{0}")]
   Os(String),
 
@@ -20,11 +36,40 @@ pub enum KeytarError {
 
   #[error("[keytar-rs] A UTF-16 error has occurred:\n\n{0}")]
   Utf16(String),
+
+  #[error("[keytar-rs] A JSON error has occurred:\n\n{0}")]
+  Json(String),
+}
+
+impl KeytarError {
+  /// Returns the value exposed as `err.code` on the JavaScript error.
+  pub fn code(&self) -> &'static str {
+    match self {
+      KeytarError::Cancelled => "ABORT_ERR",
+      KeytarError::Locked => "ERR_KEYTAR_LOCKED",
+      KeytarError::Conflict => "ERR_KEYTAR_CONFLICT",
+      KeytarError::Unsupported(_) => "ERR_KEYTAR_UNSUPPORTED",
+      KeytarError::TimedOut => "ETIMEDOUT",
+      // Matches the napi status used before error codes were introduced
+      _ => "GenericFailure",
+    }
+  }
+
+  /// Returns the value exposed as `err.name` on the JavaScript error.
+  pub fn name(&self) -> &'static str {
+    match self {
+      KeytarError::Cancelled => "AbortError",
+      _ => "Error",
+    }
+  }
 }
 
 impl From<FromUtf8Error> for KeytarError {
   fn from(error: FromUtf8Error) -> Self {
-    KeytarError::Utf8(format!("{:?}", error))
+    // The bytes may hold a secret, so only the position of the invalid sequence is kept
+    let details = format!("{:?}", error.utf8_error());
+    error.into_bytes().zeroize();
+    KeytarError::Utf8(details)
   }
 }
 
@@ -39,3 +84,9 @@ impl From<Utf8Error> for KeytarError {
     KeytarError::Utf8(format!("{:?}", error))
   }
 }
+
+impl From<serde_json::Error> for KeytarError {
+  fn from(error: serde_json::Error) -> Self {
+    KeytarError::Json(format!("{:?}", error))
+  }
+}
@@ -0,0 +1,150 @@
+use super::cache;
+use super::error::KeytarError;
+use super::options::Options;
+use super::secret::Secret;
+use super::{delete_password, get_password, set_password};
+
+/**
+ * Helper function to build the options that address a version in the history of a credential.
+ * Previous passwords are kept as they are, without the content type, expiry time or tags of a new password.
+ */
+fn at_version(options: &Options, version: u32) -> Options {
+  Options {
+    content_type: None,
+    expires_at: None,
+    tags: None,
+    version: Some(version),
+    ..options.clone()
+  }
+}
+
+/**
+ * Reads the previous passwords of a credential.
+ * Returns: The versions and their passwords, the most recent first.
+ */
+pub fn read(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Vec<(u32, Secret)>, KeytarError> {
+  let mut versions: Vec<(u32, Secret)> = Vec::new();
+  for version in 1..=options.history.unwrap_or(0) {
+    match get_password(service, account, &at_version(options, version))? {
+      Some(password) => versions.push((version, password)),
+      None => break,
+    }
+  }
+
+  Ok(versions)
+}
+
+/**
+ * Helper function to keep the current password of a credential as version 1, if history is enabled,
+ * moving older versions up by one and dropping the oldest.
+ * Returns: Whether the versions were moved, i.e. whether a current password was stored.
+ */
+fn shift(service: &String, account: &String, options: &Options) -> Result<bool, KeytarError> {
+  let size = options.history.unwrap_or(0);
+  if size == 0 {
+    return Ok(false);
+  }
+
+  let current = Options {
+    version: None,
+    ..options.clone()
+  };
+  let mut previous = match get_password(service, account, &current)? {
+    Some(previous) => previous,
+    None => return Ok(false),
+  };
+  for version in (1..size).rev() {
+    if let Some(mut password) = get_password(service, account, &at_version(options, version))? {
+      set_password(
+        service,
+        account,
+        &mut password,
+        &at_version(options, version + 1),
+      )?;
+    }
+  }
+  set_password(service, account, &mut previous, &at_version(options, 1))?;
+  Ok(true)
+}
+
+/**
+ * Helper function to undo `shift`, moving the previous passwords of a credential back down by one.
+ * The version that `shift` dropped once the history was full cannot be restored.
+ */
+fn unshift(service: &String, account: &String, options: &Options) -> Result<(), KeytarError> {
+  let size = options.history.unwrap_or(0);
+  for version in 1..size {
+    match get_password(service, account, &at_version(options, version + 1))? {
+      Some(mut password) => {
+        set_password(
+          service,
+          account,
+          &mut password,
+          &at_version(options, version),
+        )?;
+      }
+      // The version moved up last is now a copy of the one below it
+      None => {
+        delete_password(service, account, &at_version(options, version))?;
+        return Ok(());
+      }
+    }
+  }
+  delete_password(service, account, &at_version(options, size))?;
+  Ok(())
+}
+
+/**
+ * Stores the password of a credential. If history is enabled, the password it replaces is kept
+ * as version 1 and older versions move up by one, dropping the oldest.
+ * Returns: Whether the password was stored.
+ */
+pub fn write(
+  service: &String,
+  account: &String,
+  password: &mut String,
+  options: &Options,
+) -> Result<bool, KeytarError> {
+  shift(service, account, options)?;
+
+  let written = set_password(service, account, password, options);
+  // Also after a failed write, which may have replaced the password all the same
+  cache::invalidate(service, account);
+  written
+}
+
+/**
+ * Stores the password of a credential like `write`. If storing it fails, `previous` is put back
+ * and the previous passwords move back down by one, as if nothing had been written.
+ * Returns: Whether the password was stored.
+ */
+pub fn write_or_restore(
+  service: &String,
+  account: &String,
+  password: &mut String,
+  previous: Option<&mut String>,
+  options: &Options,
+) -> Result<bool, KeytarError> {
+  let shifted = shift(service, account, options)?;
+
+  let written = set_password(service, account, password, options);
+  if written.is_err() {
+    // The failed write may have replaced the password all the same
+    if let Some(previous) = previous {
+      let current = Options {
+        version: None,
+        ..options.clone()
+      };
+      let _ = set_password(service, account, previous, &current);
+    }
+    if shifted {
+      let _ = unshift(service, account, options);
+    }
+  }
+  cache::invalidate(service, account);
+  written
+}
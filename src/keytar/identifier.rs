@@ -0,0 +1,139 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// How service and account names are normalized before a credential is stored or looked up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+  /// Converts names to Unicode Normalization Form C.
+  pub normalize: bool,
+  /// Converts names to lowercase, so that names differing only in case refer to the same credential.
+  pub case_insensitive: bool,
+  /// Removes leading and trailing whitespace from names.
+  pub trim: bool,
+}
+
+static POLICY: Mutex<Policy> = Mutex::new(Policy {
+  normalize: false,
+  case_insensitive: false,
+  trim: false,
+});
+
+/// Replaces the process-wide identifier policy.
+pub fn set_policy(policy: Policy) {
+  *POLICY.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// Returns whether a policy is set, so that credentials may be stored under other names than they are looked up with.
+pub fn is_enabled() -> bool {
+  *POLICY.lock().unwrap_or_else(|e| e.into_inner()) != Policy::default()
+}
+
+/**
+ * Applies the identifier policy to a service or account name.
+ * Names are returned unchanged unless a policy was set through `set_policy`.
+ */
+pub fn normalize(name: &str) -> Cow<'_, str> {
+  let policy = *POLICY.lock().unwrap_or_else(|e| e.into_inner());
+
+  let mut name = Cow::Borrowed(if policy.trim { name.trim() } else { name });
+  if policy.normalize && !is_nfc(&name) {
+    name = Cow::Owned(name.nfc().collect());
+  }
+  if policy.case_insensitive && name.chars().any(char::is_uppercase) {
+    name = Cow::Owned(name.to_lowercase());
+  }
+
+  name
+}
+
+/**
+ * Applies the identifier policy to both names of a combined "service/account" name,
+ * or to the whole name if it has no separator.
+ */
+pub fn normalize_joined(name: &str) -> Cow<'_, str> {
+  match split(name) {
+    Some((service, account)) => {
+      let joined = join(&normalize(&service), &normalize(&account));
+      if joined == name {
+        Cow::Borrowed(name)
+      } else {
+        Cow::Owned(joined)
+      }
+    }
+    None => normalize(name),
+  }
+}
+
+/**
+ * Escapes a service or account name for use in a combined "service/account" name.
+ * `%` is encoded as `%25` and `/` as `%2F`, so that the separator is unambiguous.
+ */
+pub fn escape(component: &str) -> Cow<'_, str> {
+  if !component.contains(['%', '/']) {
+    return Cow::Borrowed(component);
+  }
+
+  Cow::Owned(component.replace('%', "%25").replace('/', "%2F"))
+}
+
+/**
+ * Reverses `escape`. Other `%` sequences are kept as-is, so names written before escaping
+ * was introduced are read unchanged unless they contain `%25` or `%2F`.
+ */
+pub fn unescape(component: &str) -> String {
+  let mut result = String::with_capacity(component.len());
+  let mut rest = component;
+  while let Some(i) = rest.find('%') {
+    result.push_str(&rest[..i]);
+    match rest.get(i + 1..i + 3) {
+      Some(code) if code.eq_ignore_ascii_case("25") => result.push('%'),
+      Some(code) if code.eq_ignore_ascii_case("2F") => result.push('/'),
+      _ => {
+        result.push('%');
+        rest = &rest[i + 1..];
+        continue;
+      }
+    }
+    rest = &rest[i + 3..];
+  }
+  result.push_str(rest);
+  result
+}
+
+/**
+ * Builds the combined name of a credential.
+ * Returns: A string in the form "service/account", with both names escaped.
+ */
+pub fn join(service: &str, account: &str) -> String {
+  format!("{}/{}", escape(service), escape(account))
+}
+
+/**
+ * Splits a combined "service/account" name at the first `/` and unescapes both parts.
+ * Returns:
+ * Some((service, account)) if the name contains a separator,
+ * or None otherwise.
+ */
+pub fn split(name: &str) -> Option<(String, String)> {
+  let (service, account) = name.split_once('/')?;
+  Some((unescape(service), unescape(account)))
+}
+
+/**
+ * Splits a combined name the way it was split before escaping was introduced,
+ * i.e. taking the first two `/`-separated parts as they are.
+ * Returns:
+ * Some((service, account)) if this differs from the result of `split`,
+ * or None otherwise.
+ */
+pub fn legacy_split(name: &str) -> Option<(String, String)> {
+  let mut parts = name.split('/');
+  let legacy = (parts.next()?.to_owned(), parts.next()?.to_owned());
+  if split(name).as_ref() == Some(&legacy) {
+    None
+  } else {
+    Some(legacy)
+  }
+}
@@ -0,0 +1,20 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+/// Number of locks that credentials are distributed over.
+const STRIPES: usize = 64;
+
+static LOCKS: [Mutex<()>; STRIPES] = [const { Mutex::new(()) }; STRIPES];
+
+/**
+ * Locks a credential for a read-modify-write, e.g. a conditional write.
+ * Only serializes operations of this process; other processes can still change the credential.
+ * Returns: A guard that releases the lock when dropped.
+ */
+pub fn lock(service: &str, account: &str) -> MutexGuard<'static, ()> {
+  let mut hasher = DefaultHasher::new();
+  (service, account).hash(&mut hasher);
+  let stripe = &LOCKS[(hasher.finish() % STRIPES as u64) as usize];
+  stripe.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -1,5 +1,13 @@
 extern crate security_framework;
+use super::change::ChangeCallback;
 use super::error::KeytarError;
+use super::identifier;
+use super::options::Options;
+use super::search::{merge, Found, Search, Tagged};
+use super::secret::{self, Secret};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use zeroize::{Zeroize, Zeroizing};
 
 use security_framework::{
   item::{ItemClass, ItemSearchOptions},
@@ -17,82 +25,398 @@ impl From<security_framework::base::Error> for KeytarError {
   }
 }
 
+/// Attributes of a keychain item, as returned by `simplify_dict`. When the item was searched for with
+/// its data, they include a copy of its password as "v_Data", which is wiped once they are dropped.
+struct ItemAttributes(HashMap<String, String>);
+
+impl Deref for ItemAttributes {
+  type Target = HashMap<String, String>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl DerefMut for ItemAttributes {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.0
+  }
+}
+
+impl Drop for ItemAttributes {
+  fn drop(&mut self) {
+    if let Some(data) = self.0.get_mut("v_Data") {
+      data.zeroize();
+    }
+  }
+}
+
+/**
+ * Helper function to build the keychain service of a credential, or of a version in its history.
+ * Versions are kept under a separate service, so they are not found by service searches.
+ */
+fn keychain_service(service: &str, options: &Options) -> String {
+  match options.version {
+    Some(version) => version_service(service, version),
+    None => service.to_owned(),
+  }
+}
+
+/**
+ * Helper function to build the keychain service of a version in the history of a credential,
+ * in the form "service#history/version". A service that is itself named like this is taken for a version.
+ */
+fn version_service(service: &str, version: u32) -> String {
+  format!("{}#history/{}", service, version)
+}
+
+/**
+ * Helper function to check whether a keychain service keeps a previous password of a credential.
+ */
+fn is_version_service(service: &str) -> bool {
+  match service.rsplit_once("#history/") {
+    Some((_, version)) => !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()),
+    None => false,
+  }
+}
+
+/// Name of the credential store used by this backend.
+pub const BACKEND: &str = "keychain";
+
+/**
+ * Stores a password as a generic keychain item.
+ * Keychain items carry no content type, so `options.content_type` is not recorded,
+ * and no expiry time or tags, so credentials that expire or have tags are rejected.
+ */
 pub fn set_password(
   service: &String,
   account: &String,
   password: &mut String,
+  options: &Options,
 ) -> Result<bool, KeytarError> {
+  if options.expires_at.is_some() {
+    return Err(KeytarError::InvalidArg {
+      argument: "expiresAt".to_owned(),
+      details: "Credentials that expire are not supported by the macOS keychain".to_owned(),
+    });
+  }
+
+  if options.tags.as_ref().is_some_and(|tags| !tags.is_empty()) {
+    return Err(KeytarError::InvalidArg {
+      argument: "tags".to_owned(),
+      details: "Tags are not supported by the macOS keychain".to_owned(),
+    });
+  }
+
   let keychain = SecKeychain::default().unwrap();
+  let service = keychain_service(service, options);
   match keychain.set_generic_password(service.as_str(), account.as_str(), password.as_bytes()) {
     Ok(()) => Ok(true),
     Err(err) => Err(KeytarError::from(err)),
   }
 }
 
-pub fn get_password(service: &String, account: &String) -> Result<Option<String>, KeytarError> {
+pub fn get_password(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<Secret>, KeytarError> {
   let keychain = SecKeychain::default().unwrap();
+  let service = keychain_service(service, options);
   match keychain.find_generic_password(service.as_str(), account.as_str()) {
-    Ok((pw, _)) => Ok(Some(String::from_utf8(pw.to_owned())?)),
+    Ok((pw, _)) => Ok(Some(secret::from_utf8(Zeroizing::new(pw.to_owned()))?)),
     Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(None),
     Err(err) => Err(KeytarError::from(err)),
   }
 }
 
-pub fn find_password(service: &String) -> Result<Option<String>, KeytarError> {
-  let cred_attrs: Vec<&str> = service.split("/").collect();
-  if cred_attrs.len() < 2 {
-    return Err(KeytarError::InvalidArg {
-      argument: "service".to_owned(),
-      details: "Invalid format for service string; must be in format 'SERVICE/ACCOUNT'".to_owned(),
-    });
-  }
+/**
+ * Reads the password of a credential along with its expiry time, which is always unset
+ * since keychain items never expire.
+ */
+pub fn get_credential(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<(Secret, Option<u64>)>, KeytarError> {
+  Ok(get_password(service, account, options)?.map(|password| (password, None)))
+}
+
+pub fn find_password(service: &String, _options: &Options) -> Result<Option<Secret>, KeytarError> {
+  let (cred_service, cred_account) = match identifier::split(service) {
+    Some(cred_attrs) => cred_attrs,
+    None => {
+      return Err(KeytarError::InvalidArg {
+        argument: "service".to_owned(),
+        details: "Invalid format for service string; must be in format 'SERVICE/ACCOUNT'".to_owned(),
+      })
+    }
+  };
 
   let keychain = SecKeychain::default().unwrap();
-  match keychain.find_generic_password(cred_attrs[0], cred_attrs[1]) {
+  let mut result = keychain.find_generic_password(cred_service.as_str(), cred_account.as_str());
+  // Names containing "/" used to be split at every separator
+  if let Some((legacy_service, legacy_account)) = identifier::legacy_split(service) {
+    if matches!(&result, Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND) {
+      result = keychain.find_generic_password(legacy_service.as_str(), legacy_account.as_str());
+    }
+  }
+
+  match result {
     Ok((pw, _)) => {
-      let pw_str = String::from_utf8(pw.to_owned())?;
+      let pw_str = secret::from_utf8(Zeroizing::new(pw.to_owned()))?;
       return Ok(Some(pw_str));
     }
     Err(err) => Err(KeytarError::from(err)),
   }
 }
 
-pub fn delete_password(service: &String, account: &String) -> Result<bool, KeytarError> {
+/**
+ * Deletes a credential, including all of its previous passwords, or a single version of its history.
+ */
+pub fn delete_password(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<bool, KeytarError> {
   let keychain = SecKeychain::default().unwrap();
-  match keychain.find_generic_password(service.as_str(), account.as_str()) {
+  let keychain_service = keychain_service(service, options);
+  let deleted = match keychain.find_generic_password(keychain_service.as_str(), account.as_str()) {
     Ok((_, item)) => {
       item.delete();
-      return Ok(true);
+      true
     },
-    Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(false),
-    Err(err) => Err(KeytarError::from(err)),
+    Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => false,
+    Err(err) => return Err(KeytarError::from(err)),
+  };
+
+  // Versions are numbered from 1 without gaps, so every version is deleted by looking them up
+  // in turn, also those beyond the configured history size
+  if options.version.is_none() {
+    for version in 1.. {
+      match keychain.find_generic_password(version_service(service, version).as_str(), account.as_str()) {
+        Ok((_, item)) => item.delete(),
+        Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => break,
+        Err(err) => return Err(KeytarError::from(err)),
+      }
+    }
   }
+
+  Ok(deleted)
+}
+
+/**
+ * Helper function to parse a keychain date in the form "2023-05-01 12:34:56 +0000".
+ * Returns: The date in seconds since the Unix epoch, or 0 if it could not be parsed.
+ */
+fn parse_keychain_date(date: Option<&String>) -> u64 {
+  let parse = |date: &str| -> Option<u64> {
+    let field = |range: std::ops::Range<usize>| date.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, min, sec) = (field(11..13)?, field(14..16)?, field(17..19)?);
+
+    // Days since the Unix epoch for a date in the proleptic Gregorian calendar
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 86400 + hour * 3600 + min * 60 + sec).ok()
+  };
+
+  date.and_then(|date| parse(date)).unwrap_or(0)
 }
 
+/**
+ * Finds the credentials of a service under each of the given names of the service.
+ * An account found under an earlier name hides the same account under later names.
+ * Returns: Whether more credentials follow the requested page.
+ */
 pub fn find_credentials(
-  service: &String,
-  credentials: &mut Vec<(String, String)>,
+  services: &[String],
+  credentials: &mut Vec<(String, Secret)>,
+  warnings: &mut Vec<String>,
+  search: &Search,
+  _options: &Options,
 ) -> Result<bool, KeytarError> {
+  // Fetch no more items than needed to fill the page and tell whether more follow,
+  // unless all items need to be sorted first
+  let limit = match search.page.limit {
+    Some(limit) if search.sort.is_none() && !search.dedupe => {
+      search.page.offset.saturating_add(limit).saturating_add(1).min(i32::MAX as usize)
+    }
+    _ => i32::MAX as usize,
+  };
+
+  let mut groups: Vec<Vec<Found<ItemAttributes>>> = Vec::new();
+  for service in services {
+    match ItemSearchOptions::new()
+      .class(ItemClass::generic_password())
+      .label(service.as_str())
+      .limit(limit as i64)
+      .load_attributes(true)
+      .load_data(true)
+      .load_refs(true)
+      .search() {
+      Ok(search_results) => groups.push(
+        search_results
+          .iter()
+          .filter_map(|result| result.simplify_dict().map(ItemAttributes))
+          .filter_map(|result_map| match result_map.get("acct") {
+            Some(account) => Some(Found {
+              account: account.to_owned(),
+              created: parse_keychain_date(result_map.get("cdat")),
+              modified: parse_keychain_date(result_map.get("mdat")),
+              item: result_map,
+            }),
+            None => {
+              warnings.push(format!("Skipped item in '{}': missing account", service));
+              None
+            }
+          })
+          .collect(),
+      ),
+      Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => {},
+      Err(err) => return Err(KeytarError::from(err)),
+    }
+  }
+
+  let (found, more) = search.select(merge(groups));
+  for mut result in found {
+    match result.item.remove("v_Data") {
+      Some(password) => credentials.push((result.account, Zeroizing::new(password))),
+      None => warnings.push(format!(
+        "Skipped item for account '{}': password could not be read",
+        result.account
+      )),
+    }
+  }
+  Ok(more)
+}
+
+/**
+ * Deletes the expired credentials of a service.
+ * Returns: Always 0, since keychain items never expire.
+ */
+pub fn purge_expired(_service: Option<&String>, _options: &Options) -> Result<u32, KeytarError> {
+  Ok(0)
+}
+
+/**
+ * Finds the credentials whose attributes and tags match the given ones.
+ * Keychain items only carry a service and an account, and never tags,
+ * so searches for tags or for any other attribute find nothing.
+ */
+pub fn search_credentials(
+  attributes: &HashMap<String, String>,
+  tags: &HashMap<String, String>,
+  _options: &Options,
+) -> Result<Vec<Tagged>, KeytarError> {
+  if !tags.is_empty()
+    || attributes
+      .keys()
+      .any(|name| name != "service" && name != "account")
+  {
+    return Ok(Vec::new());
+  }
+
+  let mut search = ItemSearchOptions::new();
+  search
+    .class(ItemClass::generic_password())
+    .limit(i32::MAX as i64)
+    .load_attributes(true)
+    .load_data(true);
+  if let Some(service) = attributes.get("service") {
+    search.label(service.as_str());
+  }
+
+  match search.search() {
+    Ok(search_results) => Ok(
+      search_results
+        .iter()
+        .filter_map(|result| result.simplify_dict().map(ItemAttributes))
+        .filter_map(|mut result_map| {
+          let credential = Tagged {
+            service: result_map.remove("svce")?,
+            account: result_map.remove("acct")?,
+            password: Zeroizing::new(result_map.remove("v_Data")?),
+            tags: HashMap::new(),
+          };
+          // Previous passwords are only returned by the history of a credential
+          let matches = !is_version_service(&credential.service)
+            && attributes.get("service").map_or(true, |s| *s == credential.service)
+            && attributes.get("account").map_or(true, |a| *a == credential.account);
+          matches.then_some(credential)
+        })
+        .collect(),
+    ),
+    Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(Vec::new()),
+    Err(err) => Err(KeytarError::from(err)),
+  }
+}
+
+/**
+ * Finds the credentials of an account across all services, or of all accounts starting with `account`.
+ * Returns: The service, account and, if requested, password of each credential.
+ */
+pub fn find_by_account(
+  account: &String,
+  prefix: bool,
+  secrets: bool,
+  _options: &Options,
+) -> Result<Vec<(String, String, Option<Secret>)>, KeytarError> {
   match ItemSearchOptions::new()
     .class(ItemClass::generic_password())
-    .label(service.as_str())
     .limit(i32::MAX as i64)
     .load_attributes(true)
-    .load_data(true)
-    .load_refs(true)
-    .search() {
-    Ok(search_results) => {
-      for result in search_results {
-        if let Some(result_map) = result.simplify_dict() {
-          credentials.push((
-            result_map.get("acct").unwrap().to_owned(),
-            result_map.get("v_Data").unwrap().to_owned(),
-          ))
-        }
-      }
-      return Ok(!credentials.is_empty());
-    },
-    Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(false),
+    .load_data(secrets)
+    .search()
+  {
+    Ok(search_results) => Ok(
+      search_results
+        .iter()
+        .filter_map(|result| result.simplify_dict().map(ItemAttributes))
+        .filter_map(|mut result_map| {
+          let service = result_map.remove("svce")?;
+          let found_account = result_map.remove("acct")?;
+          // Previous passwords are only returned by the history of a credential
+          let matches = !is_version_service(&service)
+            && (found_account == *account
+              || (prefix && found_account.starts_with(account.as_str())));
+          if !matches {
+            return None;
+          }
+
+          let password = match secrets {
+            true => Some(Zeroizing::new(result_map.remove("v_Data")?)),
+            false => None,
+          };
+          Some((service, found_account, password))
+        })
+        .collect(),
+    ),
+    Err(err) if err.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(Vec::new()),
     Err(err) => Err(KeytarError::from(err)),
   }
 }
+
+/**
+ * Watches the credentials of a service, or of all services, for changes.
+ * Returns: An error, since the macOS keychain does not report changes to generic passwords.
+ */
+pub fn watch(
+  _service: Option<&String>,
+  _callback: ChangeCallback,
+  _options: &Options,
+) -> Result<u64, KeytarError> {
+  Err(KeytarError::Unsupported(
+    "The macOS keychain does not report changes to generic passwords".to_owned(),
+  ))
+}
+
+/**
+ * Stops a watch started by `watch`.
+ */
+pub fn unwatch(_id: u64) {}
@@ -1,14 +1,24 @@
+pub mod cache;
+pub mod cancel;
+pub mod change;
+pub mod condition;
 pub mod error;
+pub mod history;
+pub mod identifier;
+pub mod lock;
+pub mod options;
+pub mod search;
+pub mod secret;
 
 cfg_if::cfg_if! {
     if #[cfg(target_os = "windows")] {
         pub mod win;
-        pub use win::{delete_password, find_credentials, find_password, get_password, set_password};
+        pub use win::{BACKEND, delete_password, find_by_account, find_credentials, find_password, get_credential, get_password, purge_expired, search_credentials, set_password, unwatch, watch};
     } else if #[cfg(target_os = "macos")] {
         pub mod mac;
-        pub use mac::{delete_password, find_credentials, find_password, get_password, set_password};
+        pub use mac::{BACKEND, delete_password, find_by_account, find_credentials, find_password, get_credential, get_password, purge_expired, search_credentials, set_password, unwatch, watch};
     } else if #[cfg(any(target_os = "freebsd", target_os = "linux"))] {
         pub mod unix;
-        pub use unix::{delete_password, find_credentials, find_password, get_password, set_password};
+        pub use unix::{BACKEND, delete_password, find_by_account, find_credentials, find_password, get_credential, get_password, purge_expired, search_credentials, set_password, unwatch, watch};
     }
 }
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::cancel::CancelToken;
+use super::error::KeytarError;
+
+/// Settings that control how a single keyring operation is carried out.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+  /// Whether the backend may show an interactive prompt to unlock a locked keyring.
+  /// When unset, the backend keeps its default behavior.
+  pub allow_prompt: Option<bool>,
+  /// Cancels the operation when triggered. Only used per call, never as a default.
+  pub cancel: Option<CancelToken>,
+  /// Time in milliseconds after which the operation is cancelled; `0` disables the timeout.
+  pub timeout: Option<u32>,
+  /// Alias of the collection that new credentials are stored in and searched for (libsecret only).
+  /// Set per `Keyring`, never as a default.
+  pub collection: Option<String>,
+  /// Label of new credentials, where `{service}` and `{account}` are replaced with their names (libsecret only).
+  /// Set per `Keyring`, never as a default.
+  pub label_template: Option<String>,
+  /// Content type of a stored secret, e.g. `application/json`. Recorded with the credential where
+  /// the backend supports it; only used per call, never as a default.
+  pub content_type: Option<String>,
+  /// Time in milliseconds since the Unix epoch after which a new credential is treated as absent.
+  /// Only used per call, never as a default.
+  pub expires_at: Option<u64>,
+  /// Tags of a new credential, as names and values. Only used per call, never as a default.
+  pub tags: Option<HashMap<String, String>>,
+  /// Number of previous passwords kept per credential; none are kept when unset.
+  pub history: Option<u32>,
+  /// Version of a previous password in the history of a credential, where `1` is the most recent.
+  /// Addresses the credential's current password when unset. Only used per call, never as a default.
+  pub version: Option<u32>,
+}
+
+static DEFAULTS: Mutex<Options> = Mutex::new(Options {
+  allow_prompt: None,
+  cancel: None,
+  timeout: None,
+  collection: None,
+  label_template: None,
+  content_type: None,
+  expires_at: None,
+  tags: None,
+  history: None,
+  version: None,
+});
+
+/// Returns the current time in milliseconds since the Unix epoch, as used for `expires_at`.
+pub fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|elapsed| elapsed.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+/// Returns the process-wide defaults used for settings a call does not specify.
+pub fn defaults() -> Options {
+  DEFAULTS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Replaces the process-wide defaults used for settings a call does not specify.
+pub fn set_defaults(defaults: Options) {
+  *DEFAULTS.lock().unwrap_or_else(|e| e.into_inner()) = defaults;
+}
+
+impl Options {
+  /// Fills in any settings left unset with the process-wide defaults.
+  pub fn with_defaults(self) -> Options {
+    let defaults = defaults();
+    Options {
+      allow_prompt: self.allow_prompt.or(defaults.allow_prompt),
+      cancel: self.cancel,
+      timeout: self.timeout.or(defaults.timeout),
+      collection: self.collection,
+      label_template: self.label_template,
+      content_type: self.content_type,
+      expires_at: self.expires_at,
+      tags: self.tags,
+      history: self.history.or(defaults.history),
+      version: self.version,
+    }
+  }
+
+  /// Starts the timeout of the operation, if one is set.
+  pub fn start_timeout(mut self) -> Options {
+    if let Some(timeout) = self.timeout.filter(|timeout| *timeout > 0) {
+      self
+        .cancel
+        .get_or_insert_with(CancelToken::new)
+        .cancel_after(Duration::from_millis(timeout as u64));
+    }
+
+    self
+  }
+
+  /**
+   * Runs a backend operation unless it was already cancelled.
+   * A cancelled operation reports whether it was aborted or timed out.
+   */
+  pub fn run<T>(
+    &self,
+    operation: impl FnOnce() -> Result<T, KeytarError>,
+  ) -> Result<T, KeytarError> {
+    self.check_cancelled()?;
+    match operation() {
+      Err(KeytarError::Cancelled) => {
+        self.check_cancelled()?;
+        Err(KeytarError::Cancelled)
+      }
+      result => result,
+    }
+  }
+
+  /// Returns a `Cancelled` error if the operation has been cancelled.
+  pub fn check_cancelled(&self) -> Result<(), KeytarError> {
+    match &self.cancel {
+      Some(cancel) => cancel.check(),
+      None => Ok(()),
+    }
+  }
+}
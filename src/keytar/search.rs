@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use super::secret::Secret;
+
+/// Selects a slice of the credentials found by a search, e.g. one page of an iteration.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Page {
+  /// Number of credentials to skip.
+  pub offset: usize,
+  /// Maximum number of credentials to return. When unset, all remaining credentials are returned.
+  pub limit: Option<usize>,
+}
+
+impl Page {
+  /// Returns whether more results follow the page, given the total number of results.
+  pub fn has_more(&self, total: usize) -> bool {
+    match self.limit {
+      Some(limit) => total > self.offset.saturating_add(limit),
+      None => false,
+    }
+  }
+
+  /// Selects the results that fall within the page.
+  pub fn select<T>(&self, results: impl IntoIterator<Item = T>) -> impl Iterator<Item = T> {
+    results
+      .into_iter()
+      .skip(self.offset)
+      .take(self.limit.unwrap_or(usize::MAX))
+  }
+}
+
+/// Order in which the credentials found by a search are returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+  Account,
+  Created,
+  Modified,
+}
+
+/// Controls which of the credentials found by a search are returned, and in what order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Search {
+  /// Sorts the credentials in ascending order. When unset, the backend's order is kept.
+  pub sort: Option<SortKey>,
+  /// Returns a single credential per account, choosing the most recently modified.
+  pub dedupe: bool,
+  pub page: Page,
+}
+
+/// An item found by a backend search, along with the metadata used to sort and deduplicate it.
+pub struct Found<T> {
+  pub account: String,
+  /// Creation time in seconds since the Unix epoch; `0` if unknown.
+  pub created: u64,
+  /// Last modification time in seconds since the Unix epoch; `0` if unknown.
+  pub modified: u64,
+  pub item: T,
+}
+
+impl Search {
+  /**
+   * Deduplicates, sorts and pages the items found by a backend search.
+   * Items are only selected here, so backends can defer loading secrets until afterwards.
+   * Returns: The selected items, and whether more deduplicated items follow the page.
+   */
+  pub fn select<T>(&self, mut found: Vec<Found<T>>) -> (Vec<Found<T>>, bool) {
+    if self.dedupe {
+      let mut latest: HashMap<String, usize> = HashMap::new();
+      for (i, item) in found.iter().enumerate() {
+        match latest.get(&item.account) {
+          Some(&j) if found[j].modified >= item.modified => {}
+          _ => {
+            latest.insert(item.account.clone(), i);
+          }
+        }
+      }
+
+      let mut i = 0;
+      found.retain(|item| {
+        let keep = latest[&item.account] == i;
+        i += 1;
+        keep
+      });
+    }
+
+    match self.sort {
+      Some(SortKey::Account) => found.sort_by(|a, b| a.account.cmp(&b.account)),
+      Some(SortKey::Created) => {
+        found.sort_by(|a, b| a.created.cmp(&b.created).then(a.account.cmp(&b.account)))
+      }
+      Some(SortKey::Modified) => {
+        found.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.account.cmp(&b.account)))
+      }
+      None => {}
+    }
+
+    let more = self.page.has_more(found.len());
+    (self.page.select(found).collect(), more)
+  }
+}
+
+/**
+ * Merges the items found under each name of a service, e.g. under the name given by the identifier
+ * policy and under the name it was stored with before the policy was enabled.
+ * An account found under an earlier name hides the same account under later names.
+ */
+pub fn merge<T>(groups: Vec<Vec<Found<T>>>) -> Vec<Found<T>> {
+  let mut merged: Vec<Found<T>> = Vec::new();
+  for group in groups {
+    let seen = merged.len();
+    for item in group {
+      if !merged[..seen]
+        .iter()
+        .any(|other| other.account == item.account)
+      {
+        merged.push(item);
+      }
+    }
+  }
+  merged
+}
+
+/// A credential found by an attribute search, along with its tags.
+pub struct Tagged {
+  pub service: String,
+  pub account: String,
+  pub password: Secret,
+  pub tags: HashMap<String, String>,
+}
@@ -0,0 +1,16 @@
+use zeroize::Zeroizing;
+
+use super::error::KeytarError;
+
+/// A password held in memory, wiped when it is dropped.
+pub type Secret = Zeroizing<String>;
+
+/**
+ * Helper function to convert the bytes of a secret read from a credential store into a password.
+ * The bytes are wiped whether or not they are valid UTF-8.
+ */
+pub fn from_utf8(mut bytes: Zeroizing<Vec<u8>>) -> Result<Secret, KeytarError> {
+  String::from_utf8(std::mem::take(&mut *bytes))
+    .map(Zeroizing::new)
+    .map_err(KeytarError::from)
+}
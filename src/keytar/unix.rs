@@ -1,14 +1,34 @@
 extern crate libsecret;
+use gio::{prelude::DBusProxyExt, GioFuture, GioFutureResult};
 use glib::translate::{FromGlibPtrContainer, ToGlibPtr};
+use glib::{Cast, ToVariant};
 use libsecret::{
-  prelude::CollectionExtManual, traits::ItemExt, SearchFlags, Service, ServiceFlags,
+  prelude::{CollectionExtManual, ServiceExtManual},
+  traits::{CollectionExt, ItemExt},
+  Collection, Item, SearchFlags, Service, ServiceFlags,
 };
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::future::Future;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
 
+use super::change::{Change, ChangeCallback, ChangeKind};
 use super::error::KeytarError;
+use super::identifier;
+use super::options::{self, Options};
+use super::search::{merge, Found, Search, Tagged};
+use super::secret::{self, Secret};
+use zeroize::Zeroizing;
 
 impl From<glib::error::Error> for KeytarError {
   fn from(err: glib::error::Error) -> Self {
+    if err.matches(gio::IOErrorEnum::Cancelled) {
+      return KeytarError::Cancelled;
+    }
+
     KeytarError::Library {
       name: "glib".to_owned(),
       details: format!("{:?}", err.message().to_owned()),
@@ -16,151 +36,1145 @@ impl From<glib::error::Error> for KeytarError {
   }
 }
 
+/// Name of the schema that credentials are stored with.
+const SCHEMA_NAME: &str = "org.freedesktop.Secret.Generic";
+
+/// Prefix of the attributes that hold the tags of a credential, e.g. "tag:env".
+const TAG_PREFIX: &str = "tag:";
+
+/// Attributes defined by the schema, all of them strings.
+const SCHEMA_ATTRIBUTES: [&str; 5] = [
+  "service",
+  "account",
+  // Only set on items that do not hold plain text, e.g. JSON values
+  "content-type",
+  // Time in milliseconds since the Unix epoch, only set on items that expire
+  "expires-at",
+  // Only set on items that keep a previous password of a credential
+  "version",
+];
+
 fn get_schema() -> libsecret::Schema {
   libsecret::Schema::new(
-    "org.freedesktop.Secret.Generic",
+    SCHEMA_NAME,
     libsecret::SchemaFlags::NONE,
-    HashMap::from([
-      ("service", libsecret::SchemaAttributeType::String),
-      ("account", libsecret::SchemaAttributeType::String),
-    ]),
+    SCHEMA_ATTRIBUTES
+      .iter()
+      .map(|name| (*name, libsecret::SchemaAttributeType::String))
+      .collect(),
   )
 }
 
-fn get_attribute_map<'a>(service: &'a str, account: &'a str) -> HashMap<&'a str, &'a str> {
+fn get_attribute_map(service: &str, account: &str) -> HashMap<String, String> {
   HashMap::from([
-    ("service", service),
-    ("account", account),
+    ("service".to_owned(), service.to_owned()),
+    ("account".to_owned(), account.to_owned()),
   ])
 }
 
-pub fn set_password(
-  service: &String,
-  account: &String,
-  password: &String,
-) -> Result<bool, KeytarError> {
-  let attributes = get_attribute_map(service.as_str(), account.as_str());
+/**
+ * Helper function to choose the schema to pass to libsecret along with the given attributes.
+ * libsecret rejects attributes that the schema does not define, such as tags. Those attributes are
+ * passed without a schema instead, matching the schema through its "xdg:schema" attribute.
+ */
+fn get_schema_for(attributes: &mut HashMap<String, String>) -> Option<libsecret::Schema> {
+  if attributes
+    .keys()
+    .all(|name| SCHEMA_ATTRIBUTES.contains(&name.as_str()))
+  {
+    return Some(get_schema());
+  }
+
+  attributes.insert("xdg:schema".to_owned(), SCHEMA_NAME.to_owned());
+  None
+}
 
-  let collection = libsecret::COLLECTION_DEFAULT;
-  match libsecret::password_store_sync(
-    Some(&get_schema()),
-    attributes,
-    Some(collection),
-    format!("{}/{}", service, account).as_str(),
-    password.as_str(),
-    gio::Cancellable::NONE,
-  ) {
-    Ok(_) => Ok(true),
-    Err(err) => Err(KeytarError::from(err)),
+/**
+ * Helper function to read the tags of an item, given its attributes.
+ */
+fn get_tags(attributes: &HashMap<String, String>) -> HashMap<String, String> {
+  attributes
+    .iter()
+    .filter_map(|(name, value)| Some((name.strip_prefix(TAG_PREFIX)?.to_owned(), value.clone())))
+    .collect()
+}
+
+/**
+ * Helper function to borrow an attribute map in the form expected by libsecret.
+ */
+fn as_str_map(attributes: &HashMap<String, String>) -> HashMap<&str, &str> {
+  attributes
+    .iter()
+    .map(|(key, value)| (key.as_str(), value.as_str()))
+    .collect()
+}
+
+static MAIN_CONTEXT: Mutex<Option<glib::MainContext>> = Mutex::new(None);
+
+/**
+ * Helper function to get the context of the GLib main loop that runs all libsecret calls.
+ * The main loop runs on its own thread, which is started on first use.
+ */
+fn get_main_context() -> glib::MainContext {
+  let mut main_context = MAIN_CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+  if let Some(context) = main_context.as_ref() {
+    return context.clone();
   }
+
+  let context = glib::MainContext::new();
+  let main_loop = glib::MainLoop::new(Some(&context), false);
+  let thread_context = context.clone();
+  thread::Builder::new()
+    .name("keytar-glib".to_owned())
+    .spawn(move || {
+      thread_context.push_thread_default();
+      main_loop.run();
+      thread_context.pop_thread_default();
+    })
+    .expect("failed to spawn keytar GLib main loop thread");
+
+  *main_context = Some(context.clone());
+  context
 }
 
-pub fn get_password(service: &String, account: &String) -> Result<Option<String>, KeytarError> {
-  let attributes = get_attribute_map(service.as_str(), account.as_str());
+/**
+ * Helper function to run an operation on the GLib main loop thread and wait for its result.
+ * The future is created on the main loop thread, so it may hold GObjects that are not thread-safe.
+ */
+fn run_on_main_loop<T, F, Fut>(operation: F) -> Result<T, KeytarError>
+where
+  T: Send + 'static,
+  F: FnOnce() -> Fut + Send + 'static,
+  Fut: Future<Output = Result<T, KeytarError>> + 'static,
+{
+  let (sender, receiver) = mpsc::channel();
+  let context = get_main_context();
+  context.clone().invoke(move || {
+    context.spawn_local(async move {
+      let _ = sender.send(operation().await);
+    });
+  });
 
-  match libsecret::password_lookup_sync(Some(&get_schema()), attributes, gio::Cancellable::NONE) {
-    Ok(pw) => match pw {
-      Some(pass) => Ok(Some(pass.to_string())),
-      None => Ok(None),
-    },
-    Err(err) => Err(KeytarError::from(err)),
+  receiver
+    .recv()
+    .unwrap_or_else(|_| Err(KeytarError::Os("The GLib main loop has stopped".to_owned())))
+}
+
+/**
+ * Helper function to run a libsecret async call as a future.
+ * The GCancellable of the call is cancelled together with the operation.
+ */
+fn call_async<O, T>(
+  obj: &O,
+  options: &Options,
+  schedule: impl FnOnce(&O, &gio::Cancellable, GioFutureResult<T>) + 'static,
+) -> impl Future<Output = T>
+where
+  O: Clone + 'static,
+  T: 'static,
+{
+  let cancel = options.cancel.clone();
+  GioFuture::new(obj, move |obj, cancellable, result| {
+    if let Some(cancel) = cancel {
+      let cancellable = cancellable.clone();
+      cancel.on_cancel(move |_| cancellable.cancel());
+    }
+
+    schedule(obj, cancellable, result);
+  })
+}
+
+thread_local! {
+  /// Connection to the Secret Service that is reused across calls on the main loop thread.
+  static SHARED_SERVICE: RefCell<Option<Service>> = RefCell::new(None);
+  /// Signal subscriptions of the active watches, by watch ID.
+  static WATCHES: RefCell<HashMap<u64, (gio::DBusConnection, gio::SignalSubscriptionId)>> =
+    RefCell::new(HashMap::new());
+}
+
+static NEXT_WATCH_ID: AtomicU64 = AtomicU64::new(0);
+
+/**
+ * Helper function to get the shared Secret Service connection, connecting on first use.
+ * The connection has an open session for transferring secrets and loaded collections.
+ */
+async fn get_service(options: &Options) -> Result<Service, glib::Error> {
+  if let Some(secret_service) = SHARED_SERVICE.with(|shared| shared.borrow().clone()) {
+    return Ok(secret_service);
   }
+
+  let secret_service = call_async(&(), options, |_, cancellable, result| {
+    Service::get(
+      ServiceFlags::OPEN_SESSION | ServiceFlags::LOAD_COLLECTIONS,
+      Some(cancellable),
+      move |res| result.resolve(res),
+    );
+  })
+  .await?;
+
+  SHARED_SERVICE.with(|shared| *shared.borrow_mut() = Some(secret_service.clone()));
+  Ok(secret_service)
 }
 
-pub fn find_password(service: &String) -> Result<Option<String>, KeytarError> {
-  let attributes = if service.contains("/") && service.len() > 1 {
-    // In format "service/account"
-    let values: Vec<&str> = service.split("/").collect();
-    get_attribute_map(values[0], values[1])
-  } else {
-    HashMap::from([("service", service.as_str())])
+/**
+ * Helper function to drop the shared connection, so that the next call connects again.
+ */
+fn reset_service() {
+  SHARED_SERVICE.with(|shared| shared.borrow_mut().take());
+  Service::disconnect();
+}
+
+/**
+ * Helper function to check whether an error means that the connection to the Secret Service was lost,
+ * e.g. because the D-Bus session bus or the keyring daemon restarted.
+ * Other D-Bus errors, such as a locked item or denied access, are failures of the call itself.
+ */
+fn is_connection_error(err: &glib::Error) -> bool {
+  err.matches(gio::IOErrorEnum::Closed)
+    || err.matches(gio::DBusError::Disconnected)
+    || err.matches(gio::DBusError::NoReply)
+    || err.matches(gio::DBusError::ServiceUnknown)
+    || err.matches(gio::DBusError::NameHasNoOwner)
+}
+
+/**
+ * Helper function to run an operation on the shared Secret Service connection.
+ * If the connection was lost, the operation is retried once on a new connection.
+ */
+async fn with_service<T, F, Fut>(options: &Options, operation: F) -> Result<T, KeytarError>
+where
+  F: Fn(Service) -> Fut,
+  Fut: Future<Output = Result<T, glib::Error>>,
+{
+  match operation(get_service(options).await?).await {
+    Err(err) if is_connection_error(&err) => {
+      reset_service();
+      Ok(operation(get_service(options).await?).await?)
+    }
+    result => Ok(result?),
+  }
+}
+
+/**
+ * Helper function to get the alias of the collection that credentials are stored in.
+ */
+fn get_collection_alias(options: &Options) -> &str {
+  options
+    .collection
+    .as_deref()
+    .unwrap_or(libsecret::COLLECTION_DEFAULT)
+}
+
+/**
+ * Helper function to open the collection that credentials are stored in; the default collection unless configured otherwise.
+ */
+async fn get_collection(
+  options: &Options,
+  flags: libsecret::CollectionFlags,
+) -> Result<Option<Collection>, KeytarError> {
+  let alias = get_collection_alias(options).to_owned();
+  with_service(options, |secret_service| {
+    let alias = alias.clone();
+    call_async(
+      &secret_service,
+      options,
+      move |secret_service, cancellable, result| {
+        Collection::for_alias(
+          Some(secret_service),
+          alias.as_str(),
+          flags,
+          Some(cancellable),
+          move |res| result.resolve(res),
+        );
+      },
+    )
+  })
+  .await
+}
+
+/**
+ * Helper function to read the attributes of an item.
+ */
+fn get_item_attributes(item: &Item) -> HashMap<String, String> {
+  unsafe {
+    let attrs = libsecret_sys::secret_item_get_attributes(item.to_glib_none().0);
+    FromGlibPtrContainer::from_glib_full(attrs)
+  }
+}
+
+/**
+ * Helper function to read the secret of an unlocked item.
+ * Returns:
+ * Some(bytes) if the item's secret was loaded,
+ * or None otherwise.
+ */
+fn get_item_secret(item: &Item) -> Option<Zeroizing<Vec<u8>>> {
+  item.secret().map(|secret| {
+    let bytes = Zeroizing::new(secret.get());
+    unsafe {
+      libsecret_sys::secret_value_unref(secret.as_ptr() as *mut _);
+    }
+    bytes
+  })
+}
+
+/**
+ * Helper function to load the secrets of the given items in a single request.
+ */
+async fn load_secrets(items: &[Item], options: &Options) -> Result<(), KeytarError> {
+  if items.is_empty() {
+    return Ok(());
+  }
+
+  let items = items.to_vec();
+  call_async(&(), options, move |_, cancellable, result| {
+    Item::load_secrets(&items, Some(cancellable), move |res| result.resolve(res));
+  })
+  .await?;
+
+  Ok(())
+}
+
+/**
+ * Helper function to unlock the given items, prompting the user if the Secret Service needs to.
+ * Returns: The object paths of the items that are unlocked afterwards.
+ */
+async fn unlock_items(items: &[Item], options: &Options) -> Result<Vec<String>, KeytarError> {
+  let mut unlocked: Vec<String> = items
+    .iter()
+    .filter(|item| !item.is_locked())
+    .map(|item| item.object_path().to_string())
+    .collect();
+  let objects: Vec<gio::DBusProxy> = items
+    .iter()
+    .filter(|item| item.is_locked())
+    .map(|item| item.clone().upcast())
+    .collect();
+  if objects.is_empty() {
+    return Ok(unlocked);
+  }
+
+  let newly_unlocked = with_service(options, |secret_service| {
+    let objects = objects.clone();
+    call_async(
+      &secret_service,
+      options,
+      move |secret_service, cancellable, result| {
+        secret_service.unlock(&objects, Some(cancellable), move |res| result.resolve(res));
+      },
+    )
+  })
+  .await?;
+  unlocked.extend(
+    newly_unlocked
+      .iter()
+      .map(|object| object.object_path().to_string()),
+  );
+
+  Ok(unlocked)
+}
+
+/**
+ * Helper function to check whether the collection is locked, for calls that must not prompt.
+ * Returns: An error if the collection is locked and prompting is disabled.
+ */
+async fn check_collection(options: &Options) -> Result<(), KeytarError> {
+  if options.allow_prompt != Some(false) {
+    return Ok(());
+  }
+
+  match get_collection(options, libsecret::CollectionFlags::NONE).await? {
+    Some(col) if col.is_locked() => Err(KeytarError::Locked),
+    _ => Ok(()),
+  }
+}
+
+/**
+ * Helper function to get the expiry time of an item in milliseconds since the Unix epoch, if it expires.
+ */
+fn get_expiry(item: &Item) -> Option<u64> {
+  get_item_attributes(item)
+    .get("expires-at")
+    .and_then(|expires_at| expires_at.parse::<u64>().ok())
+}
+
+/**
+ * Helper function to check whether an item has expired, given its attributes.
+ * Items without a valid expiry time never expire.
+ */
+fn is_expired(attributes: &HashMap<String, String>) -> bool {
+  match attributes
+    .get("expires-at")
+    .and_then(|expires_at| expires_at.parse::<u64>().ok())
+  {
+    Some(expires_at) => expires_at <= options::now_millis(),
+    None => false,
+  }
+}
+
+/**
+ * Helper function to check whether an item holds a credential stored by this library, given its attributes.
+ * Other applications may store items with the same generic schema, but without a service and account.
+ */
+fn is_credential(attributes: &HashMap<String, String>) -> bool {
+  attributes.get("xdg:schema").map(String::as_str) == Some(SCHEMA_NAME)
+    && attributes.contains_key("service")
+    && attributes.contains_key("account")
+}
+
+/**
+ * Helper function to search for the items that match the given attributes.
+ */
+async fn search_items(
+  attributes: HashMap<String, String>,
+  flags: SearchFlags,
+  options: &Options,
+) -> Result<Vec<Item>, KeytarError> {
+  with_service(options, |secret_service| {
+    let mut attributes = attributes.clone();
+    let schema = get_schema_for(&mut attributes);
+    call_async(
+      &secret_service,
+      options,
+      move |secret_service, cancellable, result| {
+        secret_service.search(
+          schema.as_ref(),
+          as_str_map(&attributes),
+          flags,
+          Some(cancellable),
+          move |res| result.resolve(res),
+        );
+      },
+    )
+  })
+  .await
+}
+
+/**
+ * Helper function to search for the items that match the given attributes within the collection
+ * that credentials are stored in.
+ * Returns: The matching items, or none if the collection does not exist.
+ */
+async fn search_collection(
+  attributes: HashMap<String, String>,
+  flags: SearchFlags,
+  options: &Options,
+) -> Result<Vec<Item>, KeytarError> {
+  let collection = match get_collection(options, libsecret::CollectionFlags::NONE).await? {
+    Some(col) => col,
+    None => return Ok(Vec::new()),
+  };
+
+  let mut attributes = attributes;
+  let schema = get_schema_for(&mut attributes);
+  Ok(
+    call_async(
+      &collection,
+      options,
+      move |collection, cancellable, result| {
+        collection.search(
+          schema.as_ref(),
+          as_str_map(&attributes),
+          flags,
+          Some(cancellable),
+          move |res| result.resolve(res),
+        );
+      },
+    )
+    .await?,
+  )
+}
+
+/**
+ * Helper function to search for the items of a credential: within the configured collection if a
+ * `Keyring` sets one, or in all collections otherwise, like `secret_service_lookup` does.
+ */
+async fn search_credential_items(
+  attributes: HashMap<String, String>,
+  flags: SearchFlags,
+  options: &Options,
+) -> Result<Vec<Item>, KeytarError> {
+  match options.collection {
+    Some(_) => search_collection(attributes, flags, options).await,
+    None => search_items(attributes, flags, options).await,
+  }
+}
+
+/**
+ * Helper function to delete a single item.
+ */
+async fn delete_item(item: &Item, options: &Options) -> Result<(), KeytarError> {
+  call_async(item, options, move |item, cancellable, result| {
+    item.delete(Some(cancellable), move |res| result.resolve(res));
+  })
+  .await?;
+
+  Ok(())
+}
+
+/**
+ * Helper function to delete the items of a credential that a newly stored item with the given attributes did not replace.
+ * An item is only replaced if all of its attributes match, e.g. not if it was stored with a different content type or tags.
+ * Only the collection that the new item was stored in is searched.
+ */
+async fn delete_replaced_items(
+  service_account: HashMap<String, String>,
+  attributes: &HashMap<String, String>,
+  options: &Options,
+) -> Result<(), KeytarError> {
+  for item in search_collection(service_account, SearchFlags::ALL, options).await? {
+    let item_attributes = get_item_attributes(&item);
+    // Other versions of the credential are kept
+    if item_attributes.get("version") != attributes.get("version") {
+      continue;
+    }
+    if ["content-type", "expires-at"]
+      .iter()
+      .any(|key| item_attributes.get(*key) != attributes.get(*key))
+      || get_tags(&item_attributes) != get_tags(attributes)
+    {
+      delete_item(&item, options).await?;
+    }
+  }
+
+  Ok(())
+}
+
+/**
+ * Helper function to look up the password of the first item that matches the given attributes
+ * and the version given in the options.
+ * When a prompt policy is set, locked items either fail fast or are unlocked through a prompt.
+ * Expired items are treated as absent until they are purged.
+ * Returns: The password and the expiry time of the item, if it expires.
+ */
+async fn lookup_password(
+  attributes: HashMap<String, String>,
+  options: &Options,
+) -> Result<Option<(Secret, Option<u64>)>, KeytarError> {
+  let flags = match options.allow_prompt {
+    Some(false) => SearchFlags::ALL | SearchFlags::LOAD_SECRETS,
+    // Without a prompt policy, unlock the item like `secret_service_lookup` does
+    _ => SearchFlags::ALL | SearchFlags::UNLOCK | SearchFlags::LOAD_SECRETS,
   };
+  let items = search_credential_items(attributes, flags, options).await?;
 
-  match libsecret::password_lookup_sync(Some(&get_schema()), attributes, gio::Cancellable::NONE) {
-    Ok(pw) => match pw {
-      Some(pass) => Ok(Some(pass.to_string())),
+  let version = options.version.map(|version| version.to_string());
+  match items
+    .iter()
+    .find(|item| get_item_attributes(item).get("version") == version.as_ref())
+  {
+    Some(item) if is_expired(&get_item_attributes(item)) => Ok(None),
+    // The item stays locked if prompting was disabled or the user dismissed the prompt
+    Some(item) if item.is_locked() => match options.allow_prompt {
+      Some(_) => Err(KeytarError::Locked),
       None => Ok(None),
     },
-    Err(err) => Err(KeytarError::from(err)),
+    Some(item) => match get_item_secret(item) {
+      Some(bytes) => Ok(Some((secret::from_utf8(bytes)?, get_expiry(item)))),
+      None => Ok(None),
+    },
+    None => Ok(None),
   }
 }
 
-pub fn delete_password(service: &String, account: &String) -> Result<bool, KeytarError> {
-  match libsecret::password_clear_sync(
-    Some(&get_schema()),
-    get_attribute_map(service.as_str(), account.as_str()),
-    gio::Cancellable::NONE,
-  ) {
-    Ok(_) => Ok(true),
-    Err(err) => match err.kind() {
-      Some(glib::KeyFileError::NotFound) => Ok(false),
-      _ => Err(KeytarError::from(err)),
-    },
+/// Name of the credential store used by this backend.
+pub const BACKEND: &str = "libsecret";
+
+pub fn set_password(
+  service: &String,
+  account: &String,
+  password: &String,
+  options: &Options,
+) -> Result<bool, KeytarError> {
+  let label = match &options.label_template {
+    Some(template) => template
+      .replace("{service}", service)
+      .replace("{account}", account),
+    None => format!("{}/{}", service, account),
+  };
+  let service_account = get_attribute_map(service, account);
+  let mut attributes = service_account.clone();
+  if let Some(content_type) = &options.content_type {
+    attributes.insert("content-type".to_owned(), content_type.clone());
   }
+  if let Some(expires_at) = options.expires_at {
+    attributes.insert("expires-at".to_owned(), expires_at.to_string());
+  }
+  if let Some(version) = options.version {
+    attributes.insert("version".to_owned(), version.to_string());
+  }
+  for (name, value) in options.tags.iter().flatten() {
+    attributes.insert(format!("{}{}", TAG_PREFIX, name), value.clone());
+  }
+  let password = Zeroizing::new(password.clone());
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    check_collection(&options).await?;
+    with_service(&options, |secret_service| {
+      let mut attributes = attributes.clone();
+      let schema = get_schema_for(&mut attributes);
+      let label = label.clone();
+      let collection = get_collection_alias(&options).to_owned();
+      let content_type = options.content_type.as_deref().unwrap_or("text/plain");
+      // libsecret copies the password into a buffer of its own, and into the D-Bus message that
+      // transfers it. Neither can be wiped from here, so they stay in memory until reused.
+      let value = libsecret::Value::new(password.as_str(), content_type);
+      call_async(
+        &secret_service,
+        &options,
+        move |secret_service, cancellable, result| {
+          secret_service.store(
+            schema.as_ref(),
+            as_str_map(&attributes),
+            Some(collection.as_str()),
+            label.as_str(),
+            &value,
+            Some(cancellable),
+            move |res| result.resolve(res),
+          );
+        },
+      )
+    })
+    .await?;
+    // Stale items are only deleted once the new one is stored, so a failed store keeps the credential
+    delete_replaced_items(service_account, &attributes, &options).await?;
+
+    Ok(true)
+  })
 }
 
-pub fn find_credentials(
+pub fn get_password(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<Secret>, KeytarError> {
+  Ok(get_credential(service, account, options)?.map(|(password, _)| password))
+}
+
+/**
+ * Reads the password of a credential along with its expiry time in milliseconds since the Unix epoch,
+ * if it expires.
+ */
+pub fn get_credential(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<(Secret, Option<u64>)>, KeytarError> {
+  let attributes = get_attribute_map(service, account);
+  let options = options.clone();
+
+  run_on_main_loop(move || async move { lookup_password(attributes, &options).await })
+}
+
+pub fn find_password(service: &String, options: &Options) -> Result<Option<Secret>, KeytarError> {
+  let attributes = match identifier::split(service) {
+    // In format "service/account", with escaped names
+    Some((service, account)) => get_attribute_map(&service, &account),
+    None => HashMap::from([("service".to_owned(), service.clone())]),
+  };
+  // Names containing "/" used to be split at every separator
+  let legacy_attributes = identifier::legacy_split(service)
+    .map(|(service, account)| get_attribute_map(&service, &account));
+  let options = options.clone();
+
+  let found = run_on_main_loop(move || async move {
+    match (
+      lookup_password(attributes, &options).await?,
+      legacy_attributes,
+    ) {
+      (None, Some(legacy_attributes)) => lookup_password(legacy_attributes, &options).await,
+      (result, _) => Ok(result),
+    }
+  })?;
+  Ok(found.map(|(password, _)| password))
+}
+
+/**
+ * Deletes a credential, including its previous passwords, or a single version of its history.
+ */
+pub fn delete_password(
   service: &String,
-  credentials: &mut Vec<(String, String)>,
+  account: &String,
+  options: &Options,
 ) -> Result<bool, KeytarError> {
-  let secret_service = Service::sync(
-    ServiceFlags::OPEN_SESSION | ServiceFlags::LOAD_COLLECTIONS,
-    gio::Cancellable::NONE,
-  )?;
-  let collection = match libsecret::Collection::for_alias_sync(
-    Some(&secret_service),
-    "default",
-    libsecret::CollectionFlags::LOAD_ITEMS,
-    gio::Cancellable::NONE,
-  )? {
-    Some(col) => col,
-    None => {
-      return Err(KeytarError::Os(
-        "Unable to open libsecret collection".to_owned(),
-      ))
+  let mut attributes = get_attribute_map(service, account);
+  if let Some(version) = options.version {
+    attributes.insert("version".to_owned(), version.to_string());
+  }
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    // A configured collection is searched on its own, as `secret_service_clear` searches all of them
+    if options.collection.is_some() {
+      let items = search_collection(attributes, SearchFlags::ALL, &options).await?;
+      for item in &items {
+        delete_item(item, &options).await?;
+      }
+      return Ok(!items.is_empty());
     }
-  };
 
-  match collection.search_sync(
-    Some(&get_schema()),
-    HashMap::from([("service", service.as_str())]),
-    SearchFlags::ALL | SearchFlags::LOAD_SECRETS,
-    gio::Cancellable::NONE,
-  ) {
-    Ok(vec) => {
-      let valid_creds: Vec<(String, String)> = vec
-        .iter()
-        .filter_map(|item| {
-          let attrs: HashMap<String, String> = unsafe {
-            let attrs = libsecret_sys::secret_item_get_attributes(item.to_glib_none().0);
-            FromGlibPtrContainer::from_glib_full(attrs)
-          };
-          match item.secret() {
-            Some(secret) => {
-              let bytes = secret.get();
-              unsafe {
-                libsecret_sys::secret_value_unref(secret.as_ptr() as *mut _);
-              }
+    with_service(&options, |secret_service| {
+      let attributes = attributes.clone();
+      call_async(
+        &secret_service,
+        &options,
+        move |secret_service, cancellable, result| {
+          secret_service.clear(
+            Some(&get_schema()),
+            as_str_map(&attributes),
+            Some(cancellable),
+            move |res| result.resolve(res),
+          );
+        },
+      )
+    })
+    .await
+  })
+}
+
+/**
+ * Finds the credentials of a service within the default collection, under each of the given names
+ * of the service. An account found under an earlier name hides the same account under later names.
+ * Items that cannot be read, e.g. because another application stored them in a different format,
+ * are skipped and reported in `warnings`.
+ * Returns: Whether more credentials follow the requested page.
+ */
+pub fn find_credentials(
+  services: &[String],
+  credentials: &mut Vec<(String, Secret)>,
+  warnings: &mut Vec<String>,
+  search: &Search,
+  options: &Options,
+) -> Result<bool, KeytarError> {
+  let services = services.to_vec();
+  let search = *search;
+  let options = options.clone();
 
-              let acc = attrs.get("account").unwrap().clone();
-              let pw = String::from_utf8(bytes).unwrap_or("".to_string());
+  let (valid_creds, skipped, more) = run_on_main_loop(move || async move {
+    let collection = match get_collection(&options, libsecret::CollectionFlags::LOAD_ITEMS).await? {
+      Some(col) => col,
+      None => {
+        return Err(KeytarError::Os(format!(
+          "Unable to open libsecret collection '{}'",
+          get_collection_alias(&options)
+        )))
+      }
+    };
+
+    // Secrets are loaded separately, only for the items within the requested page
+    let mut flags = SearchFlags::ALL;
+    match options.allow_prompt {
+      Some(true) => flags |= SearchFlags::UNLOCK,
+      Some(false) if collection.is_locked() => return Err(KeytarError::Locked),
+      _ => {}
+    }
+
+    let mut warnings: Vec<String> = Vec::new();
+    let mut groups: Vec<Vec<Found<Item>>> = Vec::new();
+    for service in services {
+      let search_items = call_async(
+        &collection,
+        &options,
+        move |collection, cancellable, result| {
+          collection.search(
+            Some(&get_schema()),
+            HashMap::from([("service", service.as_str())]),
+            flags,
+            Some(cancellable),
+            move |res| result.resolve(res),
+          );
+        },
+      );
+
+      let mut vec = match search_items.await {
+        Ok(vec) => vec,
+        // The items were deleted while searching
+        Err(err) if err.message().contains("No such secret item at path") => continue,
+        Err(err) if err.matches(gio::IOErrorEnum::Cancelled) => return Err(KeytarError::Cancelled),
+        Err(err) => return Err(KeytarError::Os(err.message().to_owned())),
+      };
+      if options.allow_prompt == Some(false) && vec.iter().any(|item| item.is_locked()) {
+        return Err(KeytarError::Locked);
+      }
 
-              Some((acc, pw))
+      // Sort by D-Bus object path, so that pages are stable across calls
+      vec.sort_by_key(|item| item.object_path());
+      groups.push(
+        vec
+          .into_iter()
+          // Previous passwords are only returned by the history of a credential
+          .filter(|item| {
+            let attributes = get_item_attributes(item);
+            !is_expired(&attributes) && !attributes.contains_key("version")
+          })
+          .filter_map(|item| match get_item_attributes(&item).remove("account") {
+            Some(account) => Some(Found {
+              account,
+              created: item.created(),
+              modified: item.modified(),
+              item,
+            }),
+            None => {
+              warnings.push(format!(
+                "Skipped item {}: missing 'account' attribute",
+                item.object_path()
+              ));
+              None
             }
-            None => None,
-          }
-        })
-        .collect();
-      *credentials = valid_creds;
+          })
+          .collect(),
+      );
+    }
+    let (found, more) = search.select(merge(groups));
+
+    let items: Vec<Item> = found.iter().map(|found| found.item.clone()).collect();
+    load_secrets(&items, &options).await?;
+
+    let mut valid_creds: Vec<(String, Secret)> = Vec::new();
+    for found in found {
+      match get_item_secret(&found.item).map(secret::from_utf8) {
+        Some(Ok(pw)) => valid_creds.push((found.account, pw)),
+        Some(Err(_)) => warnings.push(format!(
+          "Skipped item {} for account '{}': secret is not valid UTF-8",
+          found.item.object_path(),
+          found.account
+        )),
+        None => warnings.push(format!(
+          "Skipped item {} for account '{}': secret could not be loaded",
+          found.item.object_path(),
+          found.account
+        )),
+      }
+    }
+
+    Ok((valid_creds, warnings, more))
+  })?;
+
+  *credentials = valid_creds;
+  warnings.extend(skipped);
+  Ok(more)
+}
+
+/**
+ * Deletes the expired credentials of a service, or of all services if none is given.
+ * Only credentials stored by this library are deleted, never those of other applications.
+ * Returns: The number of credentials deleted.
+ */
+pub fn purge_expired(service: Option<&String>, options: &Options) -> Result<u32, KeytarError> {
+  let attributes: HashMap<String, String> = service
+    .map(|service| HashMap::from([("service".to_owned(), service.clone())]))
+    .unwrap_or_default();
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    let mut purged = 0;
+    for item in search_items(attributes, SearchFlags::ALL, &options).await? {
+      let item_attributes = get_item_attributes(&item);
+      if is_expired(&item_attributes) && is_credential(&item_attributes) {
+        delete_item(&item, &options).await?;
+        purged += 1;
+      }
+    }
+
+    Ok(purged)
+  })
+}
+
+/**
+ * Finds the credentials whose attributes and tags match the given ones, in all collections.
+ * The attributes are matched by the Secret Service, so only matching items are loaded.
+ * Items that are locked or cannot be read are skipped.
+ */
+pub fn search_credentials(
+  attributes: &HashMap<String, String>,
+  tags: &HashMap<String, String>,
+  options: &Options,
+) -> Result<Vec<Tagged>, KeytarError> {
+  let mut query = attributes.clone();
+  for (name, value) in tags {
+    query.insert(format!("{}{}", TAG_PREFIX, name), value.clone());
+  }
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    let flags = match options.allow_prompt {
+      Some(false) => SearchFlags::ALL | SearchFlags::LOAD_SECRETS,
+      _ => SearchFlags::ALL | SearchFlags::UNLOCK | SearchFlags::LOAD_SECRETS,
+    };
+
+    let mut credentials: Vec<Tagged> = Vec::new();
+    for item in search_items(query, flags, &options).await? {
+      let mut item_attributes = get_item_attributes(&item);
+      // Previous passwords are only returned by the history of a credential
+      if is_expired(&item_attributes) || item_attributes.contains_key("version") {
+        continue;
+      }
+      if item.is_locked() {
+        match options.allow_prompt {
+          Some(_) => return Err(KeytarError::Locked),
+          None => continue,
+        }
+      }
+
+      let password = match get_item_secret(&item).map(secret::from_utf8) {
+        Some(Ok(password)) => password,
+        _ => continue,
+      };
+      if let (Some(service), Some(account)) = (
+        item_attributes.remove("service"),
+        item_attributes.remove("account"),
+      ) {
+        credentials.push(Tagged {
+          service,
+          account,
+          password,
+          tags: get_tags(&item_attributes),
+        });
+      }
+    }
 
-      Ok(true)
+    Ok(credentials)
+  })
+}
+
+/**
+ * Finds the credentials of an account across all services, or of all accounts starting with `account`.
+ * An exact account is matched by the Secret Service itself; a prefix is matched against all items.
+ * Secrets are only loaded if requested.
+ * Returns: The service, account and, if requested, password of each credential.
+ */
+pub fn find_by_account(
+  account: &String,
+  prefix: bool,
+  secrets: bool,
+  options: &Options,
+) -> Result<Vec<(String, String, Option<Secret>)>, KeytarError> {
+  let attributes: HashMap<String, String> = if prefix {
+    HashMap::new()
+  } else {
+    HashMap::from([("account".to_owned(), account.clone())])
+  };
+  let account = account.clone();
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    // Items are only unlocked and their secrets only loaded once they are known to match, so that
+    // a prefix search does not read the secrets of every application
+    let mut found: Vec<(String, String, Item)> = Vec::new();
+    for item in search_items(attributes, SearchFlags::ALL, &options).await? {
+      let mut item_attributes = get_item_attributes(&item);
+      // Previous passwords are only returned by the history of a credential
+      if is_expired(&item_attributes) || item_attributes.contains_key("version") {
+        continue;
+      }
+      match (
+        item_attributes.remove("service"),
+        item_attributes.remove("account"),
+      ) {
+        (Some(service), Some(item_account)) if item_account.starts_with(account.as_str()) => {
+          found.push((service, item_account, item))
+        }
+        _ => continue,
+      }
+    }
+
+    if !secrets {
+      return Ok(
+        found
+          .into_iter()
+          .map(|(service, item_account, _)| (service, item_account, None))
+          .collect(),
+      );
     }
-    Err(err) => {
-      if err.message().contains("No such secret item at path") {
-        Ok(false)
-      } else {
-        Err(KeytarError::Os(err.message().to_owned()))
+
+    let items: Vec<Item> = found.iter().map(|(_, _, item)| item.clone()).collect();
+    let unlocked = match options.allow_prompt {
+      Some(false) => items
+        .iter()
+        .filter(|item| !item.is_locked())
+        .map(|item| item.object_path().to_string())
+        .collect(),
+      _ => unlock_items(&items, &options).await?,
+    };
+    let is_unlocked = |item: &Item| unlocked.contains(&item.object_path().to_string());
+
+    let unlocked_items: Vec<Item> = items.into_iter().filter(|item| is_unlocked(item)).collect();
+    load_secrets(&unlocked_items, &options).await?;
+
+    let mut credentials: Vec<(String, String, Option<Secret>)> = Vec::new();
+    for (service, item_account, item) in found {
+      if !is_unlocked(&item) {
+        match options.allow_prompt {
+          Some(_) => return Err(KeytarError::Locked),
+          None => continue,
+        }
+      }
+      match get_item_secret(&item).map(secret::from_utf8) {
+        Some(Ok(password)) => credentials.push((service, item_account, Some(password))),
+        _ => continue,
       }
     }
+
+    Ok(credentials)
+  })
+}
+
+/**
+ * Helper function to get the names of a credential, given the attributes of its item.
+ * Returns:
+ * Some((service, account)) if the item holds the password of a credential of the watched service,
+ * or None otherwise.
+ */
+fn watched_names(
+  attributes: &HashMap<String, String>,
+  service: Option<&String>,
+) -> Option<(String, String)> {
+  // Previous passwords are only returned by the history of a credential
+  if attributes.contains_key("version") {
+    return None;
+  }
+
+  let item_service = attributes.get("service")?;
+  if service.is_some_and(|service| service != item_service) {
+    return None;
   }
+  Some((item_service.clone(), attributes.get("account")?.clone()))
+}
+
+/**
+ * Helper function to read the attributes of an item through its D-Bus properties.
+ * Unlike `get_item_attributes`, this does not need a proxy for the item, which would cache its properties.
+ */
+async fn read_item_attributes(
+  connection: &gio::DBusConnection,
+  bus_name: Option<&str>,
+  path: &str,
+) -> Option<HashMap<String, String>> {
+  let reply = connection
+    .call_future(
+      bus_name,
+      path,
+      "org.freedesktop.DBus.Properties",
+      "Get",
+      Some(&("org.freedesktop.Secret.Item", "Attributes").to_variant()),
+      Some(glib::VariantTy::new("(v)").ok()?),
+      gio::DBusCallFlags::NONE,
+      -1,
+    )
+    .await
+    .ok()?;
+  reply
+    .child_value(0)
+    .as_variant()?
+    .get::<HashMap<String, String>>()
+}
+
+/**
+ * Watches the credentials of a service, or of all services, for changes made by any process.
+ * Subscribes to the `ItemCreated`, `ItemChanged` and `ItemDeleted` signals of all collections.
+ * Deleted items can no longer be read, so their names are taken from the items seen so far.
+ * Returns: The ID of the watch, to pass to `unwatch`.
+ */
+pub fn watch(
+  service: Option<&String>,
+  callback: ChangeCallback,
+  options: &Options,
+) -> Result<u64, KeytarError> {
+  let service = service.cloned();
+  let options = options.clone();
+
+  run_on_main_loop(move || async move {
+    let secret_service = get_service(&options).await?;
+    let attributes: HashMap<String, String> = service
+      .as_ref()
+      .map(|service| HashMap::from([("service".to_owned(), service.clone())]))
+      .unwrap_or_default();
+    let known: HashMap<String, (String, String)> =
+      search_items(attributes, SearchFlags::ALL, &options)
+        .await?
+        .iter()
+        .filter_map(|item| {
+          let names = watched_names(&get_item_attributes(item), service.as_ref())?;
+          Some((item.object_path().to_string(), names))
+        })
+        .collect();
+    let known = Rc::new(RefCell::new(known));
+    let callback = Rc::new(callback);
+
+    let connection = secret_service.connection();
+    let bus_name = secret_service.name().map(|name| name.to_string());
+    let subscription = connection.signal_subscribe(
+      bus_name.as_deref(),
+      Some("org.freedesktop.Secret.Collection"),
+      None,
+      None,
+      None,
+      gio::DBusSignalFlags::NONE,
+      move |connection, _, _, _, signal, parameters| {
+        let kind = match signal {
+          "ItemCreated" => ChangeKind::Created,
+          "ItemChanged" => ChangeKind::Changed,
+          "ItemDeleted" => ChangeKind::Deleted,
+          _ => return,
+        };
+        let path = match parameters.child_value(0).str() {
+          Some(path) => path.to_owned(),
+          None => return,
+        };
+
+        if kind == ChangeKind::Deleted {
+          if let Some((service, account)) = known.borrow_mut().remove(&path) {
+            (callback.as_ref())(Change {
+              kind,
+              service,
+              account,
+            });
+          }
+          return;
+        }
+
+        let connection = connection.clone();
+        let bus_name = bus_name.clone();
+        let service = service.clone();
+        let known = known.clone();
+        let callback = callback.clone();
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+          let attributes = match read_item_attributes(&connection, bus_name.as_deref(), &path).await
+          {
+            Some(attributes) => attributes,
+            None => return,
+          };
+          if let Some((service, account)) = watched_names(&attributes, service.as_ref()) {
+            known
+              .borrow_mut()
+              .insert(path, (service.clone(), account.clone()));
+            (callback.as_ref())(Change {
+              kind,
+              service,
+              account,
+            });
+          }
+        });
+      },
+    );
+
+    let id = NEXT_WATCH_ID.fetch_add(1, Ordering::Relaxed);
+    WATCHES.with(|watches| watches.borrow_mut().insert(id, (connection, subscription)));
+    Ok(id)
+  })
+}
+
+/**
+ * Stops a watch started by `watch`. The subscription is removed on the main loop thread
+ * without waiting, so this can be called from any thread.
+ */
+pub fn unwatch(id: u64) {
+  get_main_context().invoke(move || {
+    if let Some((connection, subscription)) =
+      WATCHES.with(|watches| watches.borrow_mut().remove(&id))
+    {
+      connection.signal_unsubscribe(subscription);
+    }
+  });
 }
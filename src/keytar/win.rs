@@ -1,4 +1,10 @@
+use super::change::ChangeCallback;
 use super::error::KeytarError;
+use super::identifier;
+use super::options::{self, Options};
+use super::search::{merge, Found, Search, Tagged};
+use super::secret::{self, Secret};
+use std::collections::HashMap;
 use std::ffi::c_void;
 use std::result::Result;
 use windows_sys::{
@@ -13,6 +19,7 @@ use windows_sys::{
     Memory::LocalFree,
   },
 };
+use zeroize::{Zeroize, Zeroizing};
 
 impl From<WIN32_ERROR> for KeytarError {
   fn from(error: WIN32_ERROR) -> Self {
@@ -68,15 +75,220 @@ fn encode_utf16(str: &str) -> Vec<u16> {
   chars
 }
 
+/**
+ * Helper function to build the target name that was used before escaping was introduced.
+ * Returns:
+ * Some(name) if it differs from the escaped target name,
+ * or None otherwise.
+ */
+fn legacy_target_name(service: &str, account: &str) -> Option<String> {
+  let legacy = format!("{}/{}", service, account);
+  if legacy == identifier::join(service, account) {
+    None
+  } else {
+    Some(legacy)
+  }
+}
+
+/**
+ * Helper function to build the target name of a credential, or of a version in its history.
+ * Escaped names never contain a second "/", so versions cannot collide with other credentials.
+ */
+fn target_name(service: &str, account: &str, options: &Options) -> String {
+  match options.version {
+    Some(version) => format!("{}/{}", identifier::join(service, account), version),
+    None => identifier::join(service, account),
+  }
+}
+
+/**
+ * Helper function to read an attribute of a credential.
+ * Returns:
+ * Some(value) if the credential has an attribute with the given keyword and a UTF-8 value,
+ * or None otherwise.
+ */
+fn get_attribute(cred: &CREDENTIALW, keyword: &str) -> Option<String> {
+  let keyword: Vec<u16> = keyword.encode_utf16().collect();
+  for i in 0..cred.AttributeCount as usize {
+    unsafe {
+      let attribute = &*cred.Attributes.add(i);
+      let size = (0..)
+        .take_while(|&i| *attribute.Keyword.offset(i) != 0)
+        .count();
+      if std::slice::from_raw_parts(attribute.Keyword, size) != keyword.as_slice() {
+        continue;
+      }
+
+      let value = std::slice::from_raw_parts(attribute.Value, attribute.ValueSize as usize);
+      return String::from_utf8(value.to_vec()).ok();
+    }
+  }
+
+  None
+}
+
+/**
+ * Helper function to read all attributes of a credential that have a UTF-8 value.
+ */
+fn get_attributes(cred: &CREDENTIALW) -> HashMap<String, String> {
+  let mut attributes: HashMap<String, String> = HashMap::new();
+  for i in 0..cred.AttributeCount as usize {
+    unsafe {
+      let attribute = &*cred.Attributes.add(i);
+      let size = (0..)
+        .take_while(|&i| *attribute.Keyword.offset(i) != 0)
+        .count();
+      let keyword = String::from_utf16(std::slice::from_raw_parts(attribute.Keyword, size));
+      let value = std::slice::from_raw_parts(attribute.Value, attribute.ValueSize as usize);
+      if let (Ok(keyword), Ok(value)) = (keyword, String::from_utf8(value.to_vec())) {
+        attributes.insert(keyword, value);
+      }
+    }
+  }
+
+  attributes
+}
+
+/**
+ * Helper function to get the expiry time of a credential in milliseconds since the Unix epoch,
+ * from its "expires-at" attribute.
+ */
+fn get_expiry(cred: &CREDENTIALW) -> Option<u64> {
+  get_attribute(cred, "expires-at").and_then(|expires_at| expires_at.parse::<u64>().ok())
+}
+
+/**
+ * Helper function to check whether a credential has expired, given its "expires-at" attribute.
+ * Credentials without a valid expiry time never expire.
+ */
+fn is_expired(cred: &CREDENTIALW) -> bool {
+  match get_expiry(cred) {
+    Some(expires_at) => expires_at <= options::now_millis(),
+    None => false,
+  }
+}
+
+/**
+ * Helper function to read a null-terminated UTF-16 string of a credential.
+ * Returns:
+ * Some(string) if the pointer is set and the string is valid UTF-16,
+ * or None otherwise.
+ */
+unsafe fn from_wide(ptr: PWSTR) -> Option<String> {
+  if ptr.is_null() {
+    return None;
+  }
+
+  let size = (0..).take_while(|&i| *ptr.offset(i) != 0).count();
+  String::from_utf16(std::slice::from_raw_parts(ptr, size)).ok()
+}
+
+/**
+ * Helper function to get the names of a credential stored by this library: a generic credential
+ * whose target name is in the form "service/account", with the account as its user name.
+ * Credentials stored before escaping was introduced have the names as they are in their target name,
+ * so their service or account may contain "/" or "%2F" themselves.
+ * Credentials of other applications, e.g. "git:https://github.com", are not in this form.
+ * Returns:
+ * Some((service, account)) if the credential was stored by this library,
+ * or None otherwise.
+ */
+fn own_names(cred: &CREDENTIALW) -> Option<(String, String)> {
+  if cred.Type != CRED_TYPE_GENERIC {
+    return None;
+  }
+
+  let (target_name, user_name) =
+    unsafe { (from_wide(cred.TargetName)?, from_wide(cred.UserName)?) };
+  if let Some((service, account)) = identifier::split(&target_name) {
+    if account == user_name {
+      return Some((service, account));
+    }
+  }
+
+  let service = target_name
+    .strip_suffix(user_name.as_str())?
+    .strip_suffix('/')?;
+  Some((service.to_owned(), user_name))
+}
+
+/**
+ * Helper function to check whether a credential keeps a previous password of another credential.
+ */
+fn is_version(cred: &CREDENTIALW) -> bool {
+  get_attribute(cred, "version").is_some()
+}
+
+/**
+ * Helper function to wipe the secret of a credential read from the Credential Manager,
+ * before the buffer holding it is freed.
+ */
+unsafe fn wipe_secret(cred: &CREDENTIALW) {
+  if !cred.CredentialBlob.is_null() {
+    std::slice::from_raw_parts_mut(cred.CredentialBlob, cred.CredentialBlobSize as usize).zeroize();
+  }
+}
+
+/**
+ * Helper function to free the credentials returned by `CredEnumerateW`, after wiping their secrets.
+ */
+unsafe fn free_credentials(creds: *mut *mut CREDENTIALW, count: u32) {
+  for i in 0..count {
+    wipe_secret(&**creds.offset(i as isize));
+  }
+  CredFree(creds as *const c_void);
+}
+
+/// Prefix of the attributes that hold the tags of a credential, e.g. "tag:env".
+const TAG_PREFIX: &str = "tag:";
+
+/// Name of the credential store used by this backend.
+pub const BACKEND: &str = "credential-manager";
+
 pub fn set_password(
   service: &String,
   account: &String,
   password: &mut String,
+  options: &Options,
 ) -> Result<bool, KeytarError> {
   // Build WinAPI strings and object parameters from arguments
-  let target_bytes = encode_utf16(format!("{}/{}", service, account).as_str());
+  let target_bytes = encode_utf16(target_name(service, account, options).as_str());
   let username_bytes = encode_utf16(account.as_str());
 
+  // Record the content type of values that are not plain text, e.g. JSON values,
+  // the time in milliseconds since the Unix epoch at which the credential expires,
+  // the version of a previous password and the tags of the credential
+  let tags = options
+    .tags
+    .iter()
+    .flatten()
+    .map(|(name, value)| (format!("{}{}", TAG_PREFIX, name), Some(value.clone())));
+  let attribute_values: Vec<(Vec<u16>, String)> = [
+    ("content-type", options.content_type.clone()),
+    (
+      "expires-at",
+      options.expires_at.map(|expires_at| expires_at.to_string()),
+    ),
+    (
+      "version",
+      options.version.map(|version| version.to_string()),
+    ),
+  ]
+  .into_iter()
+  .map(|(keyword, value)| (keyword.to_owned(), value))
+  .chain(tags)
+  .filter_map(|(keyword, value)| Some((encode_utf16(&keyword), value?)))
+  .collect();
+  let mut attributes: Vec<CREDENTIAL_ATTRIBUTEW> = attribute_values
+    .iter()
+    .map(|(keyword, value)| CREDENTIAL_ATTRIBUTEW {
+      Keyword: keyword.as_ptr() as PWSTR,
+      Flags: 0,
+      ValueSize: value.len() as u32,
+      Value: value.as_ptr() as *mut u8,
+    })
+    .collect();
+
   let cred = CREDENTIALW {
     Flags: 0,
     Type: CRED_TYPE_GENERIC,
@@ -89,8 +301,12 @@ pub fn set_password(
     Persist: CRED_PERSIST_ENTERPRISE,
     CredentialBlobSize: password.len() as u32,
     CredentialBlob: password.as_ptr() as *mut u8,
-    AttributeCount: 0,
-    Attributes: std::ptr::null_mut(),
+    AttributeCount: attributes.len() as u32,
+    Attributes: if attributes.is_empty() {
+      std::ptr::null_mut()
+    } else {
+      attributes.as_mut_ptr()
+    },
     TargetAlias: std::ptr::null_mut(),
     UserName: username_bytes.as_ptr() as PWSTR,
   };
@@ -109,12 +325,50 @@ pub fn set_password(
     return Err(KeytarError::from(error_code));
   }
 
+  // Remove the entry stored under the unescaped name, so the credential is not found twice
+  if let (None, Some(legacy_target)) = (options.version, legacy_target_name(service, account)) {
+    delete_credential(&legacy_target)?;
+  }
+
   Ok(true)
 }
 
-pub fn get_password(service: &String, account: &String) -> Result<Option<String>, KeytarError> {
+pub fn get_password(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<Secret>, KeytarError> {
+  Ok(get_credential(service, account, options)?.map(|(password, _)| password))
+}
+
+/**
+ * Reads the password of a credential along with its expiry time in milliseconds since the Unix epoch,
+ * if it expires.
+ */
+pub fn get_credential(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<Option<(Secret, Option<u64>)>, KeytarError> {
+  match read_credential(&target_name(service, account, options))? {
+    None if options.version.is_none() => match legacy_target_name(service, account) {
+      // Fall back to the entry stored under the unescaped name
+      Some(legacy_target) => read_credential(&legacy_target),
+      None => Ok(None),
+    },
+    password => Ok(password),
+  }
+}
+
+/**
+ * Helper function to read the password of a generic credential.
+ * Returns:
+ * Some((password, expiry time)) if a credential with the given target name exists,
+ * or None otherwise.
+ */
+fn read_credential(target: &str) -> Result<Option<(Secret, Option<u64>)>, KeytarError> {
   let mut cred: *mut CREDENTIALW = std::ptr::null_mut::<CREDENTIALW>();
-  let target_name = encode_utf16(format!("{}/{}", service, account).as_str());
+  let target_name = encode_utf16(target);
 
   // Attempt to read credential from user's credential set
   let read_result: i32;
@@ -142,21 +396,110 @@ pub fn get_password(service: &String, account: &String) -> Result<Option<String>
 
   // Build buffer for credential secret and return as UTF-8 string
   unsafe {
-    let bytes =
-      std::slice::from_raw_parts((*cred).CredentialBlob, (*cred).CredentialBlobSize as usize);
+    // Expired credentials are treated as absent until they are purged
+    if is_expired(&*cred) {
+      wipe_secret(&*cred);
+      CredFree(cred as *const c_void);
+      return Ok(None);
+    }
+
+    let bytes = Zeroizing::new(
+      std::slice::from_raw_parts((*cred).CredentialBlob, (*cred).CredentialBlobSize as usize)
+        .to_vec(),
+    );
 
+    let expires_at = get_expiry(&*cred);
+
+    wipe_secret(&*cred);
     CredFree(cred as *const c_void);
-    return match String::from_utf8(bytes.to_vec()) {
-      Ok(str) => Ok(Some(str)),
-      Err(err) => Err(KeytarError::Utf8(
-        format!("Failed to convert credential to UTF-8: {}", err).to_owned(),
-      )),
-    };
+    return secret::from_utf8(bytes).map(|password| Some((password, expires_at)));
+  }
+}
+
+/**
+ * Deletes a credential, including all of its previous passwords, or a single version of its history.
+ */
+pub fn delete_password(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> Result<bool, KeytarError> {
+  let mut deleted = delete_credential(&target_name(service, account, options))?;
+  if options.version.is_none() {
+    if let Some(legacy_target) = legacy_target_name(service, account) {
+      deleted |= delete_credential(&legacy_target)?;
+    }
+    // Every version is deleted, also those beyond the configured history size
+    for version_target in find_version_targets(service, account)? {
+      delete_credential(&version_target)?;
+    }
+  }
+
+  Ok(deleted)
+}
+
+/**
+ * Helper function to find the target names of all versions in the history of a credential,
+ * which are in the form "service/account/version".
+ */
+fn find_version_targets(service: &str, account: &str) -> Result<Vec<String>, KeytarError> {
+  let prefix = format!("{}/", identifier::join(service, account));
+  let filter_bytes = encode_utf16(format!("{}*", prefix).as_str());
+  let mut count: u32 = 0;
+  let mut creds: *mut *mut CREDENTIALW = std::ptr::null_mut::<*mut CREDENTIALW>();
+
+  let find_result: i32;
+  unsafe {
+    find_result = CredEnumerateW(
+      filter_bytes.as_ptr() as PCWSTR,
+      0u32,
+      &mut count,
+      &mut creds as *mut *mut *mut CREDENTIALW,
+    );
+  }
+
+  if find_result != TRUE {
+    let error_code: WIN32_ERROR;
+    unsafe {
+      error_code = GetLastError();
+    }
+    if error_code == ERROR_NOT_FOUND {
+      return Ok(Vec::new());
+    }
+
+    return Err(KeytarError::from(error_code));
+  }
+
+  let mut targets: Vec<String> = Vec::new();
+  for i in 0..count {
+    unsafe {
+      let cred = &**creds.offset(i as isize);
+      // A "*" in the names would match other credentials as well
+      let target = match from_wide(cred.TargetName) {
+        Some(target) if cred.Type == CRED_TYPE_GENERIC => target,
+        _ => continue,
+      };
+      let is_version = target
+        .strip_prefix(prefix.as_str())
+        .is_some_and(|version| !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit()));
+      if is_version {
+        targets.push(target);
+      }
+    }
+  }
+
+  unsafe {
+    free_credentials(creds, count);
   }
+  Ok(targets)
 }
 
-pub fn delete_password(service: &String, account: &String) -> Result<bool, KeytarError> {
-  let target_name = encode_utf16(format!("{}/{}", service, account).as_str());
+/**
+ * Helper function to delete a generic credential.
+ * Returns: Whether a credential with the given target name was deleted.
+ */
+fn delete_credential(target: &str) -> Result<bool, KeytarError> {
+  let target_name = encode_utf16(target);
 
   // Attempt to delete credential from user's credential set
   let delete_result: i32;
@@ -182,7 +525,12 @@ pub fn delete_password(service: &String, account: &String) -> Result<bool, Keyta
   Ok(true)
 }
 
-pub fn find_password(service: &String) -> Result<Option<String>, KeytarError> {
+pub fn find_password(service: &String, options: &Options) -> Result<Option<Secret>, KeytarError> {
+  // In format "service/account", with escaped names
+  if let Some((service, account)) = identifier::split(service) {
+    return get_password(&service, &account, options);
+  }
+
   let filter = encode_utf16(format!("{}*", service).as_str());
 
   let mut count: u32 = 0;
@@ -211,35 +559,271 @@ pub fn find_password(service: &String) -> Result<Option<String>, KeytarError> {
     return Err(KeytarError::from(error_code));
   }
 
-  let cred: *const CREDENTIALW;
-  unsafe {
-    cred = *creds.offset(0);
-    let size = (*cred).CredentialBlobSize as usize;
-    let pw = String::from(std::str::from_utf8(std::slice::from_raw_parts(
-      (*cred).CredentialBlob,
-      size,
-    ))?);
-    CredFree(creds as *const c_void);
+  // Expired credentials are treated as absent until they are purged,
+  // and previous passwords are only returned by the history of a credential
+  let mut password: Result<Option<Secret>, KeytarError> = Ok(None);
+  for i in 0..count {
+    let cred: &CREDENTIALW;
+    unsafe {
+      cred = &**creds.offset(i as isize);
+    }
+    if is_expired(cred) || is_version(cred) {
+      continue;
+    }
 
-    Ok(Some(pw))
+    unsafe {
+      let size = cred.CredentialBlobSize as usize;
+      password = std::str::from_utf8(std::slice::from_raw_parts(cred.CredentialBlob, size))
+        .map(|pw| Some(Zeroizing::new(pw.to_owned())))
+        .map_err(KeytarError::from);
+    }
+    break;
+  }
+
+  unsafe {
+    free_credentials(creds, count);
   }
+  password
 }
 
+/**
+ * Helper function to convert a FILETIME into seconds since the Unix epoch.
+ */
+fn filetime_to_unix_secs(filetime: &FILETIME) -> u64 {
+  // FILETIME counts 100-nanosecond intervals since January 1, 1601
+  let intervals = ((filetime.dwHighDateTime as u64) << 32) | filetime.dwLowDateTime as u64;
+  (intervals / 10_000_000).saturating_sub(11_644_473_600)
+}
+
+/**
+ * Finds the credentials of a service under each of the given names of the service.
+ * An account found under an earlier name hides the same account under later names.
+ * Returns: Whether more credentials follow the requested page.
+ */
 pub fn find_credentials(
-  service: &String,
-  credentials: &mut Vec<(String, String)>,
+  services: &[String],
+  credentials: &mut Vec<(String, Secret)>,
+  warnings: &mut Vec<String>,
+  search: &Search,
+  _options: &Options,
 ) -> Result<bool, KeytarError> {
-  let filter_bytes: Vec<u16> = encode_utf16(format!("{}*", service).as_str());
-  let filter = filter_bytes.as_ptr() as PCWSTR;
+  // Credentials are stored under "service/account", with escaped names.
+  // Entries written before escaping was introduced use the unescaped service name.
+  let mut filters: Vec<(usize, String)> = Vec::new();
+  for (group, service) in services.iter().enumerate() {
+    filters.push((group, format!("{}/*", identifier::escape(service))));
+    if identifier::escape(service) != service.as_str() {
+      filters.push((group, format!("{}/*", service)));
+    }
+  }
+
+  let mut cred_sets: Vec<(*mut *mut CREDENTIALW, u32)> = Vec::new();
+  let mut groups: Vec<Vec<Found<&CREDENTIALW>>> = services.iter().map(|_| Vec::new()).collect();
+  for (group, filter) in filters {
+    let filter_bytes: Vec<u16> = encode_utf16(filter.as_str());
+    let filter = filter_bytes.as_ptr() as PCWSTR;
+
+    let mut count: u32 = 0;
+    let mut creds: *mut *mut CREDENTIALW = std::ptr::null_mut::<*mut CREDENTIALW>();
+
+    // Attempt to fetch user's credential set
+    let find_result: i32;
+    unsafe {
+      find_result = CredEnumerateW(
+        filter,
+        0u32,
+        &mut count,
+        &mut creds as *mut *mut *mut CREDENTIALW,
+      );
+    }
+
+    if find_result != TRUE {
+      let error_code: WIN32_ERROR;
+      unsafe {
+        error_code = GetLastError();
+      }
+      if error_code == ERROR_NOT_FOUND {
+        continue;
+      }
+
+      for (creds, count) in cred_sets {
+        unsafe {
+          free_credentials(creds, count);
+        }
+      }
+      return Err(KeytarError::from(error_code));
+    }
+    cred_sets.push((creds, count));
+
+    // Find and build matching credential list from user's credential set
+    for i in 0..count {
+      let cred: &CREDENTIALW;
+      unsafe {
+        cred = &**creds.offset(i as isize);
+      }
+
+      // Previous passwords are only returned by the history of a credential
+      if cred.UserName.is_null()
+        || cred.CredentialBlobSize == 0
+        || is_expired(cred)
+        || is_version(cred)
+      {
+        continue;
+      }
+
+      let username: String;
+      unsafe {
+        let size = (0..).take_while(|&i| *cred.UserName.offset(i) != 0).count();
+        match String::from_utf16(std::slice::from_raw_parts(cred.UserName, size)) {
+          Ok(name) => username = name,
+          Err(_) => {
+            warnings.push(format!(
+              "Skipped credential {}: user name is not valid UTF-16",
+              i
+            ));
+            continue;
+          }
+        }
+      }
+
+      // The Credential Manager only records when a credential was last written
+      let modified = filetime_to_unix_secs(&cred.LastWritten);
+      groups[group].push(Found {
+        account: username,
+        created: modified,
+        modified,
+        item: cred,
+      });
+    }
+  }
+
+  let (found, more) = search.select(merge(groups));
+  for cred in found {
+    let password: Result<&str, _>;
+    unsafe {
+      password = std::str::from_utf8(std::slice::from_raw_parts(
+        cred.item.CredentialBlob,
+        cred.item.CredentialBlobSize as usize,
+      ));
+    }
+
+    match password {
+      Ok(password) => credentials.push((cred.account, Zeroizing::new(password.to_owned()))),
+      Err(_) => warnings.push(format!(
+        "Skipped credential for account '{}': password is not valid UTF-8",
+        cred.account
+      )),
+    }
+  }
+
+  for (creds, count) in cred_sets {
+    unsafe {
+      free_credentials(creds, count);
+    }
+  }
+
+  Ok(more)
+}
+
+/**
+ * Deletes the expired credentials of a service, or of all services if none is given.
+ * Only credentials stored by this library are deleted, never those of other applications.
+ * Returns: The number of credentials deleted.
+ */
+pub fn purge_expired(service: Option<&String>, _options: &Options) -> Result<u32, KeytarError> {
+  // Entries written before escaping was introduced use the unescaped service name
+  let filters: Vec<Option<String>> = match service {
+    Some(service) if identifier::escape(service) != service.as_str() => vec![
+      Some(format!("{}/*", identifier::escape(service))),
+      Some(format!("{}/*", service)),
+    ],
+    Some(service) => vec![Some(format!("{}/*", service))],
+    None => vec![None],
+  };
+
+  let mut expired: Vec<Vec<u16>> = Vec::new();
+  for filter in filters {
+    let filter_bytes = filter.map(|filter| encode_utf16(filter.as_str()));
+    let mut count: u32 = 0;
+    let mut creds: *mut *mut CREDENTIALW = std::ptr::null_mut::<*mut CREDENTIALW>();
+
+    // Without a filter, all credentials of the user are enumerated
+    let find_result: i32;
+    unsafe {
+      find_result = CredEnumerateW(
+        filter_bytes
+          .as_ref()
+          .map_or(std::ptr::null(), |filter| filter.as_ptr() as PCWSTR),
+        0u32,
+        &mut count,
+        &mut creds as *mut *mut *mut CREDENTIALW,
+      );
+    }
+
+    if find_result != TRUE {
+      let error_code: WIN32_ERROR;
+      unsafe {
+        error_code = GetLastError();
+      }
+      if error_code == ERROR_NOT_FOUND {
+        continue;
+      }
+
+      return Err(KeytarError::from(error_code));
+    }
 
+    for i in 0..count {
+      unsafe {
+        let cred = &**creds.offset(i as isize);
+        if is_expired(cred) && own_names(cred).is_some() {
+          let size = (0..)
+            .take_while(|&i| *cred.TargetName.offset(i) != 0)
+            .count();
+          let mut target_name = std::slice::from_raw_parts(cred.TargetName, size).to_vec();
+          target_name.push(0);
+          expired.push(target_name);
+        }
+      }
+    }
+
+    unsafe {
+      free_credentials(creds, count);
+    }
+  }
+
+  let mut purged = 0;
+  for target_name in expired {
+    let delete_result: i32;
+    unsafe {
+      delete_result = CredDeleteW(target_name.as_ptr() as PCWSTR, CRED_TYPE_GENERIC, 0);
+    }
+    if delete_result == TRUE {
+      purged += 1;
+    }
+  }
+
+  Ok(purged)
+}
+
+/**
+ * Finds the credentials whose attributes and tags match the given ones.
+ * The Credential Manager can only filter by target name, so all credentials are enumerated
+ * and matched against the "service" and "account" encoded in their target names and their attributes.
+ * Credentials of other applications are skipped, so an empty query only returns those of this library.
+ */
+pub fn search_credentials(
+  attributes: &HashMap<String, String>,
+  tags: &HashMap<String, String>,
+  _options: &Options,
+) -> Result<Vec<Tagged>, KeytarError> {
   let mut count: u32 = 0;
   let mut creds: *mut *mut CREDENTIALW = std::ptr::null_mut::<*mut CREDENTIALW>();
 
-  // Attempt to fetch user's credential set
+  // Without a filter, all credentials of the user are enumerated
   let find_result: i32;
   unsafe {
     find_result = CredEnumerateW(
-      filter,
+      std::ptr::null(),
       0u32,
       &mut count,
       &mut creds as *mut *mut *mut CREDENTIALW,
@@ -252,42 +836,161 @@ pub fn find_credentials(
       error_code = GetLastError();
     }
     if error_code == ERROR_NOT_FOUND {
-      return Ok(false);
+      return Ok(Vec::new());
     }
 
     return Err(KeytarError::from(error_code));
   }
 
-  // Find and build matching credential list from user's credential set
+  let mut credentials: Vec<Tagged> = Vec::new();
   for i in 0..count {
     let cred: &CREDENTIALW;
     unsafe {
       cred = &**creds.offset(i as isize);
     }
 
-    if cred.UserName.is_null() || cred.CredentialBlobSize == 0 {
+    // Previous passwords are only returned by the history of a credential
+    if cred.Type != CRED_TYPE_GENERIC || is_expired(cred) || is_version(cred) {
       continue;
     }
 
-    let password: String;
+    let (service, account) = match own_names(cred) {
+      Some(names) => names,
+      None => continue,
+    };
+    let password: Secret;
     unsafe {
-      password = String::from(std::str::from_utf8(std::slice::from_raw_parts(
-        cred.CredentialBlob,
-        cred.CredentialBlobSize as usize,
-      ))?);
+      let blob = std::slice::from_raw_parts(cred.CredentialBlob, cred.CredentialBlobSize as usize);
+      match std::str::from_utf8(blob) {
+        Ok(pw) => password = Zeroizing::new(pw.to_owned()),
+        Err(_) => continue,
+      }
     }
 
-    let username: String;
+    let mut cred_attributes = get_attributes(cred);
+    cred_attributes.insert("service".to_owned(), service.clone());
+    cred_attributes.insert("account".to_owned(), account.clone());
+    let cred_tags: HashMap<String, String> = cred_attributes
+      .iter()
+      .filter_map(|(name, value)| Some((name.strip_prefix(TAG_PREFIX)?.to_owned(), value.clone())))
+      .collect();
+    if attributes
+      .iter()
+      .all(|(name, value)| cred_attributes.get(name) == Some(value))
+      && tags
+        .iter()
+        .all(|(name, value)| cred_tags.get(name) == Some(value))
+    {
+      credentials.push(Tagged {
+        service,
+        account,
+        password,
+        tags: cred_tags,
+      });
+    }
+  }
+
+  unsafe {
+    free_credentials(creds, count);
+  }
+  Ok(credentials)
+}
+
+/**
+ * Finds the credentials of an account across all services, or of all accounts starting with `account`.
+ * The Credential Manager can only filter by target name, which starts with the service,
+ * so all credentials are enumerated and matched locally, skipping those of other applications.
+ * Returns: The service, account and, if requested, password of each credential.
+ */
+pub fn find_by_account(
+  account: &String,
+  prefix: bool,
+  secrets: bool,
+  _options: &Options,
+) -> Result<Vec<(String, String, Option<Secret>)>, KeytarError> {
+  let mut count: u32 = 0;
+  let mut creds: *mut *mut CREDENTIALW = std::ptr::null_mut::<*mut CREDENTIALW>();
+
+  // Without a filter, all credentials of the user are enumerated
+  let find_result: i32;
+  unsafe {
+    find_result = CredEnumerateW(
+      std::ptr::null(),
+      0u32,
+      &mut count,
+      &mut creds as *mut *mut *mut CREDENTIALW,
+    );
+  }
+
+  if find_result != TRUE {
+    let error_code: WIN32_ERROR;
+    unsafe {
+      error_code = GetLastError();
+    }
+    if error_code == ERROR_NOT_FOUND {
+      return Ok(Vec::new());
+    }
+
+    return Err(KeytarError::from(error_code));
+  }
+
+  let mut credentials: Vec<(String, String, Option<Secret>)> = Vec::new();
+  for i in 0..count {
+    let cred: &CREDENTIALW;
     unsafe {
-      let size = (0..).take_while(|&i| *cred.UserName.offset(i) != 0).count();
-      username = String::from_utf16(std::slice::from_raw_parts(cred.UserName, size))?;
+      cred = &**creds.offset(i as isize);
     }
-    credentials.push((username, password));
+
+    // Previous passwords are only returned by the history of a credential
+    if cred.Type != CRED_TYPE_GENERIC || is_expired(cred) || is_version(cred) {
+      continue;
+    }
+
+    let (service, cred_account) = match own_names(cred) {
+      Some((service, cred_account))
+        if cred_account == *account || (prefix && cred_account.starts_with(account.as_str())) =>
+      {
+        (service, cred_account)
+      }
+      _ => continue,
+    };
+
+    let password = if secrets {
+      let blob: &[u8];
+      unsafe {
+        blob = std::slice::from_raw_parts(cred.CredentialBlob, cred.CredentialBlobSize as usize);
+      }
+      match std::str::from_utf8(blob) {
+        Ok(password) => Some(Zeroizing::new(password.to_owned())),
+        Err(_) => continue,
+      }
+    } else {
+      None
+    };
+    credentials.push((service, cred_account, password));
   }
 
   unsafe {
-    CredFree(creds as *const c_void);
+    free_credentials(creds, count);
   }
+  Ok(credentials)
+}
 
-  Ok(true)
+/**
+ * Watches the credentials of a service, or of all services, for changes.
+ * Returns: An error, since the Windows Credential Manager does not report changes to credentials.
+ */
+pub fn watch(
+  _service: Option<&String>,
+  _callback: ChangeCallback,
+  _options: &Options,
+) -> Result<u64, KeytarError> {
+  Err(KeytarError::Unsupported(
+    "The Windows Credential Manager does not report changes to credentials".to_owned(),
+  ))
 }
+
+/**
+ * Stops a watch started by `watch`.
+ */
+pub fn unwatch(_id: u64) {}
@@ -1,35 +1,408 @@
-use napi::bindgen_prelude::AsyncTask;
+use handle::GetSecretHandle;
+use iterator::{CredentialIterator, CredentialIteratorOptions};
+use json::{GetJSON, PatchJSON, SecretValue, SetJSON};
+use keytar::cache;
+use keytar::identifier;
+use keytar::options::{self, Options};
+use napi::{bindgen_prelude::Either, Env, JsFunction, JsObject, Result};
 use napi_derive::napi;
-use workers::{DeletePassword, FindCredentials, FindPassword, GetPassword, SetPassword};
+use std::time::Duration;
+use workers::{
+  to_js_error, to_options, to_search, to_write_options, CacheStats, CredentialId, CredentialQuery,
+  DeletePassword, FindByAccount, FindByAccountOptions, FindCredentials, FindCredentialsOptions,
+  FindPassword, GetPassword, GetPasswordHistory, KeytarConfig, KeytarOptions, PurgeExpired,
+  RollbackPassword, SearchCredentials, SetPassword, SetPasswordOptions, SwapPassword, TakePassword,
+  VerifyPassword,
+};
+use zeroize::Zeroizing;
 
+mod executor;
+mod handle;
+mod iterator;
+mod json;
+mod keyring;
 mod keytar;
+mod rotation;
+mod watch;
 mod workers;
 
+/// Maximum number of cached passwords, unless configured otherwise.
+const DEFAULT_CACHE_ENTRIES: u32 = 256;
+
 #[napi]
-fn delete_password(service: String, account: String) -> AsyncTask<DeletePassword> {
-  AsyncTask::new(DeletePassword { service, account })
+fn configure(config: KeytarConfig) {
+  // Settings that are not given keep the value of an earlier call
+  let defaults = options::defaults();
+  options::set_defaults(Options {
+    allow_prompt: config.allow_prompt.or(defaults.allow_prompt),
+    timeout: config.timeout.or(defaults.timeout),
+    history: config.history.or(defaults.history),
+    ..defaults
+  });
+
+  if let Some(concurrency) = config.concurrency {
+    executor::set_concurrency(concurrency as usize);
+  }
+
+  if let Some(policy) = config.cache {
+    cache::configure((policy.ttl > 0).then(|| cache::Config {
+      ttl: Duration::from_millis(policy.ttl as u64),
+      max_entries: policy.max_entries.unwrap_or(DEFAULT_CACHE_ENTRIES) as usize,
+    }));
+  }
+
+  if let Some(identifiers) = config.identifiers {
+    identifier::set_policy(identifier::Policy {
+      normalize: identifiers.normalize.unwrap_or(false),
+      case_insensitive: identifiers.case_insensitive.unwrap_or(false),
+      trim: identifiers.trim.unwrap_or(false),
+    });
+  }
 }
 
-#[napi]
-fn find_credentials(service: String) -> AsyncTask<FindCredentials> {
-  AsyncTask::new(FindCredentials { service })
+#[napi(ts_return_type="Promise<boolean>")]
+fn delete_password(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, DeletePassword { service, account, options }, cancel)
+}
+
+#[napi(ts_return_type="Promise<Array<AccountCredential>>")]
+fn find_by_account(
+  env: Env,
+  account: String,
+  options: Option<FindByAccountOptions>,
+) -> Result<JsObject> {
+  let (prefix, secrets) = match &options {
+    Some(options) => (options.prefix.unwrap_or(false), options.secrets.unwrap_or(false)),
+    None => (false, false),
+  };
+  let options = to_options(
+    env,
+    options.map(|options| KeytarOptions {
+      allow_prompt: options.allow_prompt,
+      signal: options.signal,
+      timeout: options.timeout,
+    }),
+  )?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    FindByAccount {
+      account,
+      prefix,
+      secrets,
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(ts_return_type="Promise<Array<Credential> & { warnings: Array<string> }>")]
+fn find_credentials(
+  env: Env,
+  service: String,
+  options: Option<FindCredentialsOptions>,
+) -> Result<JsObject> {
+  let (options, search) = to_search(options)?;
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    FindCredentials {
+      service,
+      search,
+      options,
+    },
+    cancel,
+  )
 }
 
 #[napi(ts_return_type="Promise<string | null>")]
-fn find_password(service: String) -> AsyncTask<FindPassword> {
-  AsyncTask::new(FindPassword { service })
+fn find_password(
+  env: Env,
+  service: Either<String, CredentialId>,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let service = match service {
+    Either::A(service) => service,
+    Either::B(id) => identifier::join(&id.service, &id.account),
+  };
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, FindPassword { service, options }, cancel)
 }
 
 #[napi(ts_return_type="Promise<string | null>")]
-fn get_password(service: String, account: String) -> AsyncTask<GetPassword> {
-  AsyncTask::new(GetPassword { service, account })
+fn get_password(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, GetPassword { service, account, options }, cancel)
+}
+
+#[napi]
+fn get_cache_stats() -> CacheStats {
+  let (hits, misses, size) = cache::stats();
+  CacheStats {
+    hits: hits as i64,
+    misses: misses as i64,
+    size: size as u32,
+  }
+}
+
+#[napi(ts_return_type="Promise<SecretHandle | null>")]
+fn get_secret_handle(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, GetSecretHandle { service, account, options }, cancel)
+}
+
+#[napi(js_name="getJSON", ts_return_type="Promise<any>")]
+fn get_json(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, GetJSON { service, account, options }, cancel)
+}
+
+#[napi(ts_return_type="Promise<Array<PasswordVersion>>")]
+fn get_password_history(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, GetPasswordHistory { service, account, options }, cancel)
+}
+
+#[napi(ts_return_type="AsyncIterableIterator<Credential>")]
+fn iterate_credentials(
+  env: Env,
+  service: String,
+  options: Option<CredentialIteratorOptions>,
+) -> Result<JsObject> {
+  CredentialIterator::new(env, service, options)?.into_async_iterable(env)
+}
+
+#[napi(js_name="patchJSON", ts_return_type="Promise<any>")]
+fn patch_json(
+  env: Env,
+  service: String,
+  account: String,
+  #[napi(ts_arg_type="object")] partial: serde_json::Value,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    PatchJSON {
+      service,
+      account,
+      partial: SecretValue(partial),
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(ts_return_type="Promise<number>")]
+fn purge_expired(
+  env: Env,
+  service: Option<String>,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, PurgeExpired { service, options }, cancel)
+}
+
+#[napi(ts_return_type="Promise<boolean>")]
+fn rollback_password(
+  env: Env,
+  service: String,
+  account: String,
+  version: u32,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    RollbackPassword {
+      service,
+      account,
+      version,
+      options,
+    },
+    cancel,
+  )
 }
 
 #[napi(ts_return_type="Promise<void>")]
-fn set_password(service: String, account: String, password: String) -> AsyncTask<SetPassword> {
-  AsyncTask::new(SetPassword {
-    service,
-    account,
-    password,
-  })
+fn rotate_password(
+  env: Env,
+  service: String,
+  account: String,
+  #[napi(ts_arg_type="(oldPassword: string | null) => string | Promise<string>")] callback: JsFunction,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  rotation::rotate_password(env, service, account, callback, options)
+}
+
+#[napi(ts_return_type="Promise<Array<TaggedCredential>>")]
+fn search_credentials(
+  env: Env,
+  query: CredentialQuery,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    SearchCredentials {
+      attributes: query.attributes.unwrap_or_default(),
+      tags: query.tags.unwrap_or_default(),
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(js_name="setJSON", ts_return_type="Promise<void>")]
+fn set_json(
+  env: Env,
+  service: String,
+  account: String,
+  #[napi(ts_arg_type="any")] value: serde_json::Value,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    SetJSON {
+      service,
+      account,
+      value: json::to_secret(value).map_err(|err| to_js_error(env, err))?,
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(ts_return_type="Promise<boolean>")]
+fn set_password(
+  env: Env,
+  service: String,
+  account: String,
+  password: String,
+  options: Option<SetPasswordOptions>,
+) -> Result<JsObject> {
+  let (options, write) = to_write_options(options)?;
+  let mut options = to_options(env, options)?;
+  options.expires_at = write.expires_at;
+  options.tags = write.tags;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    SetPassword {
+      service,
+      account,
+      password: Zeroizing::new(password),
+      condition: write.condition,
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(ts_return_type="Promise<string | null>")]
+fn swap_password(
+  env: Env,
+  service: String,
+  account: String,
+  password: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    SwapPassword {
+      service,
+      account,
+      password: Zeroizing::new(password),
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(ts_return_type="Promise<string | null>")]
+fn take_password(
+  env: Env,
+  service: String,
+  account: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(env, TakePassword { service, account, options }, cancel)
+}
+
+#[napi(ts_return_type="Promise<boolean>")]
+fn verify_password(
+  env: Env,
+  service: String,
+  account: String,
+  candidate: String,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  executor::spawn(
+    env,
+    VerifyPassword {
+      service,
+      account,
+      candidate: Zeroizing::new(candidate),
+      options,
+    },
+    cancel,
+  )
+}
+
+#[napi(
+  ts_args_type="service: string | undefined | null | ((change: CredentialChange) => void), callback?: (change: CredentialChange) => void, options?: KeytarOptions | undefined | null",
+  ts_return_type="Promise<() => void>"
+)]
+fn watch(
+  env: Env,
+  service: Option<Either<String, JsFunction>>,
+  callback: Option<JsFunction>,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  watch::watch(env, service, callback, options)
 }
@@ -0,0 +1,249 @@
+use napi::{Env, Error, JsFunction, JsObject, JsUnknown, Result, Task, ValueType};
+use napi_derive::napi;
+use zeroize::Zeroizing;
+
+use crate::executor;
+use crate::keytar;
+use crate::keytar::cancel::CancelToken;
+use crate::keytar::condition::secrets_equal;
+use crate::keytar::error::KeytarError;
+use crate::keytar::history;
+use crate::keytar::identifier;
+use crate::keytar::lock;
+use crate::keytar::options::Options;
+use crate::keytar::secret::Secret;
+use crate::workers::{link_signal, to_js_error, unlink_signal, with_legacy_names, KeytarOptions};
+
+/// Reads the password to rotate from the keyring itself, bypassing the cache, which may hold a stale password.
+pub struct ReadCurrent {
+  pub service: String,
+  pub account: String,
+  pub options: Options,
+}
+
+pub struct CommitRotation {
+  pub service: String,
+  pub account: String,
+  /// Password that was passed to the callback, which must still be stored.
+  pub expected: Option<Secret>,
+  pub password: Secret,
+  pub options: Options,
+}
+
+/**
+ * Helper function to attach a fulfillment handler to a promise.
+ * Returns: The promise returned by `then`.
+ */
+fn then(promise: JsObject, on_fulfilled: JsFunction) -> Result<JsObject> {
+  let then: JsFunction = promise.get_named_property("then")?;
+  then
+    .call(Some(&promise), &[on_fulfilled])?
+    .coerce_to_object()
+}
+
+/**
+ * Helper function to attach a handler to a promise that runs once it settles, either way.
+ * Returns: The promise returned by `finally`, which settles like the given one.
+ */
+fn finally(promise: JsObject, on_finally: JsFunction) -> Result<JsObject> {
+  let finally: JsFunction = promise.get_named_property("finally")?;
+  finally
+    .call(Some(&promise), &[on_finally])?
+    .coerce_to_object()
+}
+
+/**
+ * Helper function to resolve a value that is either a promise or a plain value.
+ * Returns: A promise for the value.
+ */
+fn to_promise(env: Env, value: JsUnknown) -> Result<JsObject> {
+  let promise = env
+    .get_global()?
+    .get_named_property::<JsFunction>("Promise")?
+    .coerce_to_object()?;
+  let resolve: JsFunction = promise.get_named_property("resolve")?;
+  resolve.call(Some(&promise), &[value])?.coerce_to_object()
+}
+
+/**
+ * Rotates the password of a credential: reads the current password, passes it to `callback` to
+ * obtain the new one, and stores the new password unless the current one changed in the meantime.
+ * The callback is bound as `this` of the fulfillment handler, so it needs no reference of its own.
+ * Returns: A promise that settles once the new password is stored.
+ */
+pub fn rotate_password(
+  env: Env,
+  service: String,
+  account: String,
+  callback: JsFunction,
+  options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  // The signal aborts the rotation until the callback returns the new password, but not the
+  // commit after it, as the callback may already have applied the new password elsewhere
+  let rotation = CancelToken::new();
+  let (allow_prompt, timeout) = match options {
+    Some(options) => {
+      if let Some(signal) = options.signal {
+        link_signal(env, signal, &rotation)?;
+      }
+      (options.allow_prompt, options.timeout)
+    }
+    None => (None, None),
+  };
+  let options = Options {
+    allow_prompt,
+    timeout,
+    ..Options::default()
+  }
+  .with_defaults();
+
+  // The read and the commit have a timeout each, so that the time taken by the callback does not count
+  let read_options = Options {
+    cancel: Some(rotation.child()),
+    ..options.clone()
+  }
+  .start_timeout();
+  let cancel = read_options.cancel.clone();
+  let read = executor::spawn(
+    env,
+    ReadCurrent {
+      service: service.clone(),
+      account: account.clone(),
+      options: read_options,
+    },
+    cancel,
+  )?;
+
+  let on_settled = {
+    let rotation = rotation.clone();
+    env.create_function_from_closure("onSettled", move |ctx| unlink_signal(*ctx.env, &rotation))?
+  };
+
+  let on_read = env
+    .create_function_from_closure("onRead", move |ctx| {
+      let callback = ctx.this_unchecked::<JsFunction>();
+      let current: JsUnknown = ctx.get(0)?;
+      let expected = match current.get_type()? {
+        ValueType::String => Some(Zeroizing::new(
+          current.coerce_to_string()?.into_utf8()?.into_owned()?,
+        )),
+        _ => None,
+      };
+
+      let service = service.clone();
+      let account = account.clone();
+      let options = options.clone();
+      let rotation = rotation.clone();
+      let on_password = ctx
+        .env
+        .create_function_from_closure("onPassword", move |ctx| {
+          if let Err(err) = rotation.check() {
+            return Err(to_js_error(*ctx.env, err));
+          }
+
+          let options = Options {
+            cancel: Some(CancelToken::new()),
+            ..options.clone()
+          }
+          .start_timeout();
+          let cancel = options.cancel.clone();
+          executor::spawn(
+            *ctx.env,
+            CommitRotation {
+              service: service.clone(),
+              account: account.clone(),
+              expected: expected.clone(),
+              password: Zeroizing::new(ctx.get::<String>(0)?),
+              options,
+            },
+            cancel,
+          )
+        })?;
+
+      // The callback may return the new password or a promise for it
+      let password = callback.call(None, &[ctx.get::<JsUnknown>(0)?])?;
+      then(to_promise(*ctx.env, password)?, on_password)
+    })?
+    .coerce_to_object()?;
+  let bind: JsFunction = on_read.get_named_property("bind")?;
+  let on_read: JsFunction = bind.call(Some(&on_read), &[callback])?.try_into()?;
+
+  finally(then(read, on_read)?, on_settled)
+}
+
+#[napi]
+impl Task for ReadCurrent {
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      with_legacy_names(
+        &self.service,
+        &self.account,
+        &self.options,
+        |service, account| keytar::get_password(service, account, &self.options),
+      )
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(match output {
+      Ok(Some(pw)) => env.create_string(pw.as_str())?.into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
+    })
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for CommitRotation {
+  type Output = std::result::Result<bool, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let service = identifier::normalize(&self.service).into_owned();
+      let account = identifier::normalize(&self.account).into_owned();
+      let _lock = lock::lock(&service, &account);
+      let mut current = with_legacy_names(
+        &self.service,
+        &self.account,
+        &self.options,
+        |service, account| keytar::get_password(service, account, &self.options),
+      )?;
+      let unchanged = match (&current, &self.expected) {
+        (Some(current), Some(expected)) => secrets_equal(current, expected),
+        (None, None) => true,
+        _ => false,
+      };
+      if !unchanged {
+        return Err(KeytarError::Conflict);
+      }
+
+      // A failed write puts back the previous password and its history
+      history::write_or_restore(
+        &service,
+        &account,
+        &mut self.password,
+        current.as_deref_mut(),
+        &self.options,
+      )
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(_) => Ok(env.get_null()?.into_unknown()),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
@@ -0,0 +1,168 @@
+use std::sync::{Arc, Mutex};
+
+use napi::bindgen_prelude::Either;
+use napi::threadsafe_function::{
+  ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode,
+};
+use napi::{Env, Error, JsFunction, JsObject, Result, Status, Task};
+use napi_derive::napi;
+
+use crate::executor;
+use crate::keytar;
+use crate::keytar::cancel::CancelToken;
+use crate::keytar::change::Change;
+use crate::keytar::error::KeytarError;
+use crate::keytar::identifier;
+use crate::keytar::options::Options;
+use crate::workers::{link_signal, to_js_error, to_options, unlink_signal, KeytarOptions};
+
+/// Watch registered with the backend and the callback it calls, until it is disposed.
+type ActiveWatch = Arc<Mutex<Option<(u64, ThreadsafeFunction<Change, ErrorStrategy::Fatal>)>>>;
+
+pub struct Watch {
+  pub service: Option<String>,
+  pub callback: ThreadsafeFunction<Change, ErrorStrategy::Fatal>,
+  pub options: Options,
+  /// Cancelled when the signal aborts, which disposes the watch once it is active
+  pub stop: CancelToken,
+  /// Set once the watch is registered, until it is handed to its disposer
+  pub id: Option<u64>,
+}
+
+/// A change made to a credential, passed to the callback of `watch`.
+#[napi(object)]
+pub struct CredentialChange {
+  #[napi(js_name = "type", ts_type = "'created' | 'changed' | 'deleted'")]
+  pub kind: String,
+  pub service: String,
+  pub account: String,
+}
+
+/**
+ * Watches the credentials of a service, or of all services if none is given, for changes made by any process.
+ * The callback is called on the JavaScript thread through a threadsafe function, which keeps the
+ * process alive until the returned disposer is called, or the signal of the options aborts.
+ * Returns: A promise for the disposer, once the watch is active.
+ */
+pub fn watch(
+  env: Env,
+  service: Option<Either<String, JsFunction>>,
+  callback: Option<JsFunction>,
+  mut options: Option<KeytarOptions>,
+) -> Result<JsObject> {
+  let (service, callback) = match (service, callback) {
+    (Some(Either::A(service)), Some(callback)) => (Some(service), callback),
+    (Some(Either::B(callback)), None) | (None, Some(callback)) => (None, callback),
+    _ => {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Expected an optional service name and a callback".to_owned(),
+      ))
+    }
+  };
+
+  let callback: ThreadsafeFunction<Change, ErrorStrategy::Fatal> = callback
+    .create_threadsafe_function(0, |ctx: ThreadSafeCallContext<Change>| {
+      Ok(vec![CredentialChange {
+        kind: ctx.value.kind.as_str().to_owned(),
+        service: ctx.value.service,
+        account: ctx.value.account,
+      }])
+    })?;
+  // The signal outlives the setup of the watch, so it is linked to a token of its own that
+  // neither the timeout nor the end of the setup cancels
+  let signal = options.as_mut().and_then(|options| options.signal.take());
+  let options = to_options(env, options)?;
+  let cancel = options.cancel.clone();
+  let stop = CancelToken::new();
+  if let Some(cancel) = cancel.clone() {
+    stop.on_cancel(move |_| cancel.cancel());
+  }
+  if let Some(signal) = signal {
+    link_signal(env, signal, &stop)?;
+  }
+
+  executor::spawn(
+    env,
+    Watch {
+      service,
+      callback,
+      options,
+      stop,
+      id: None,
+    },
+    cancel,
+  )
+}
+
+/**
+ * Helper function to unregister a watch and release its callback, unless it was disposed already.
+ */
+fn dispose(watch: &ActiveWatch) -> Result<()> {
+  let active = watch.lock().unwrap_or_else(|e| e.into_inner()).take();
+  if let Some((id, callback)) = active {
+    keytar::unwatch(id);
+    callback.abort()?;
+  }
+  Ok(())
+}
+
+#[napi]
+impl Task for Watch {
+  type Output = std::result::Result<u64, KeytarError>;
+  type JsValue = JsFunction;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let service = self
+      .service
+      .as_ref()
+      .map(|service| identifier::normalize(service).into_owned());
+    let callback = self.callback.clone();
+    let output = self.options.run(|| {
+      keytar::watch(
+        service.as_ref(),
+        Box::new(move |change| {
+          callback.call(change, ThreadsafeFunctionCallMode::NonBlocking);
+        }),
+        &self.options,
+      )
+    });
+    self.id = output.as_ref().ok().copied();
+    Ok(output)
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let id = output.map_err(|err| to_js_error(env, err))?;
+    self.id = None;
+
+    // The disposer releases the callback, so it can only be called once
+    let watch: ActiveWatch = Arc::new(Mutex::new(Some((id, self.callback.clone()))));
+    {
+      let watch = watch.clone();
+      self.stop.on_cancel(move |reason| {
+        if matches!(reason, KeytarError::Cancelled) {
+          let _ = dispose(&watch);
+        }
+      });
+    }
+    let stop = self.stop.clone();
+    env.create_function_from_closure("dispose", move |ctx| {
+      dispose(&watch)?;
+      unlink_signal(*ctx.env, &stop)?;
+      ctx.env.get_undefined()
+    })
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+impl Drop for Watch {
+  fn drop(&mut self) {
+    // The promise was rejected by a cancellation while the watch was being registered
+    if let Some(id) = self.id.take() {
+      keytar::unwatch(id);
+    }
+  }
+}
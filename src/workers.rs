@@ -1,53 +1,656 @@
-use napi::{Env, Error, Result, Task, JsUnknown, JsBoolean};
+use napi::{
+  Env, Error, JsBoolean, JsFunction, JsNumber, JsObject, JsUnknown, NapiRaw, NapiValue, Property,
+  Ref, Result, Status, Task,
+};
 use napi_derive::napi;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use zeroize::Zeroizing;
 
 use crate::keytar;
+use crate::keytar::cache;
+use crate::keytar::cancel::CancelToken;
+use crate::keytar::condition::{secrets_equal, WriteCondition};
+use crate::keytar::error::KeytarError;
+use crate::keytar::history;
+use crate::keytar::identifier;
+use crate::keytar::lock;
+use crate::keytar::options::{self, Options};
+use crate::keytar::search::{Page, Search, SortKey, Tagged};
+use crate::keytar::secret::Secret;
 
 pub struct SetPassword {
   pub service: String,
   pub account: String,
-  pub password: String,
+  pub password: Secret,
+  pub condition: Option<WriteCondition>,
+  pub options: Options,
 }
 
 pub struct GetPassword {
   pub service: String,
   pub account: String,
+  pub options: Options,
+}
+
+pub struct VerifyPassword {
+  pub service: String,
+  pub account: String,
+  pub candidate: Secret,
+  pub options: Options,
 }
 
 pub struct DeletePassword {
   pub service: String,
   pub account: String,
+  pub options: Options,
+}
+
+pub struct SwapPassword {
+  pub service: String,
+  pub account: String,
+  pub password: Secret,
+  pub options: Options,
+}
+
+pub struct TakePassword {
+  pub service: String,
+  pub account: String,
+  pub options: Options,
+}
+
+pub struct GetPasswordHistory {
+  pub service: String,
+  pub account: String,
+  pub options: Options,
+}
+
+pub struct RollbackPassword {
+  pub service: String,
+  pub account: String,
+  pub version: u32,
+  pub options: Options,
+}
+
+pub struct PurgeExpired {
+  pub service: Option<String>,
+  pub options: Options,
+}
+
+pub struct SearchCredentials {
+  pub attributes: HashMap<String, String>,
+  pub tags: HashMap<String, String>,
+  pub options: Options,
+}
+
+pub struct FindByAccount {
+  pub account: String,
+  pub prefix: bool,
+  pub secrets: bool,
+  pub options: Options,
 }
 
 pub struct FindCredentials {
   pub service: String,
+  pub search: Search,
+  pub options: Options,
 }
 pub struct FindPassword {
   pub service: String,
+  pub options: Options,
 }
 
+// The credential objects below only describe the shape of results. Results holding passwords are
+// built from `Secret`s instead, since napi drops the `String`s it converts without wiping them.
+#[allow(dead_code)]
 #[napi(object)]
 pub struct Credential {
   pub account: String,
   pub password: String,
 }
 
+/// A credential found by `searchCredentials`, along with its tags.
+#[allow(dead_code)]
+#[napi(object)]
+pub struct TaggedCredential {
+  pub service: String,
+  pub account: String,
+  pub password: String,
+  pub tags: HashMap<String, String>,
+}
+
+/// A credential found by `findByAccount`.
+#[allow(dead_code)]
+#[napi(object)]
+pub struct AccountCredential {
+  pub service: String,
+  pub account: String,
+  /// Only set if secrets were requested.
+  pub password: Option<String>,
+}
+
+/// A previous password of a credential.
+#[allow(dead_code)]
+#[napi(object)]
+pub struct PasswordVersion {
+  /// Version of the password, where `1` is the most recent.
+  pub version: u32,
+  pub password: String,
+}
+
+/// Identifies a credential by its service and account names, which may contain any character.
+#[napi(object)]
+pub struct CredentialId {
+  pub service: String,
+  pub account: String,
+}
+
+#[napi(object)]
+pub struct KeytarOptions {
+  /// Whether the keyring may prompt the user to unlock it.
+  /// `false` fails fast with an `ERR_KEYTAR_LOCKED` error, `true` shows the unlock prompt.
+  pub allow_prompt: Option<bool>,
+  /// Aborts the operation, rejecting with an `AbortError`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+  /// Time in milliseconds after which the operation is cancelled, rejecting with an `ETIMEDOUT` error.
+  /// `0` disables the timeout.
+  /// Only libsecret interrupts the operation; on other backends it runs to completion in the background.
+  pub timeout: Option<u32>,
+}
+
+#[napi(object)]
+pub struct SetPasswordOptions {
+  /// Same as `KeytarOptions.allowPrompt`.
+  pub allow_prompt: Option<bool>,
+  /// Same as `KeytarOptions.signal`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+  /// Same as `KeytarOptions.timeout`.
+  pub timeout: Option<u32>,
+  /// Only stores the password if none is stored yet.
+  pub if_absent: Option<bool>,
+  /// Only replaces the stored password if it equals this value, compared in constant time.
+  pub if_match: Option<String>,
+  /// Time in milliseconds since the Unix epoch after which the credential is treated as absent.
+  pub expires_at: Option<i64>,
+  /// Time in milliseconds after which the credential is treated as absent.
+  pub ttl: Option<i64>,
+  /// Tags to store with the credential, e.g. `{ env: 'prod' }`. Not supported on macOS.
+  pub tags: Option<HashMap<String, String>>,
+}
+
+#[napi(object)]
+pub struct CredentialQuery {
+  /// Attributes that the credentials must have, e.g. `{ service: 'my-app' }`.
+  pub attributes: Option<HashMap<String, String>>,
+  /// Tags that the credentials must have, e.g. `{ env: 'prod' }`.
+  pub tags: Option<HashMap<String, String>>,
+}
+
+#[napi(object)]
+pub struct FindCredentialsOptions {
+  /// Same as `KeytarOptions.allowPrompt`.
+  pub allow_prompt: Option<bool>,
+  /// Same as `KeytarOptions.signal`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+  /// Same as `KeytarOptions.timeout`.
+  pub timeout: Option<u32>,
+  /// Maximum number of credentials to return.
+  pub limit: Option<u32>,
+  /// Number of credentials to skip.
+  pub offset: Option<u32>,
+  /// Sorts the credentials in ascending order by account name, creation time or modification time.
+  #[napi(ts_type = "'account' | 'created' | 'modified'")]
+  pub sort: Option<String>,
+  /// Returns a single credential per account, choosing the most recently modified.
+  pub dedupe: Option<bool>,
+}
+
+#[napi(object)]
+pub struct FindByAccountOptions {
+  /// Same as `KeytarOptions.allowPrompt`.
+  pub allow_prompt: Option<bool>,
+  /// Same as `KeytarOptions.signal`.
+  #[napi(ts_type = "AbortSignal")]
+  pub signal: Option<JsObject>,
+  /// Same as `KeytarOptions.timeout`.
+  pub timeout: Option<u32>,
+  /// Finds all accounts starting with the given name, rather than only the account itself.
+  pub prefix: Option<bool>,
+  /// Includes the password of each credential.
+  pub secrets: Option<bool>,
+}
+
+#[napi(object)]
+pub struct KeytarConfig {
+  /// Default for `KeytarOptions.allowPrompt`.
+  pub allow_prompt: Option<bool>,
+  /// Default for `KeytarOptions.timeout`.
+  pub timeout: Option<u32>,
+  /// Number of threads running keyring operations. Defaults to 2.
+  pub concurrency: Option<u32>,
+  /// How service and account names are normalized. By default, names are used as given.
+  pub identifiers: Option<IdentifierPolicy>,
+  /// Number of previous passwords kept per credential. Defaults to 0, which keeps none.
+  pub history: Option<u32>,
+  /// Caches the passwords read by `getPassword`. By default, every read goes to the credential store.
+  pub cache: Option<CachePolicy>,
+}
+
+#[napi(object)]
+pub struct CachePolicy {
+  /// Time in milliseconds for which a password is served from the cache. `0` disables the cache.
+  pub ttl: u32,
+  /// Maximum number of cached passwords. Defaults to 256.
+  pub max_entries: Option<u32>,
+}
+
+/// Counters of the read cache, as returned by `getCacheStats`.
+#[napi(object)]
+pub struct CacheStats {
+  /// Number of reads served from the cache.
+  pub hits: i64,
+  /// Number of reads that went to the credential store while the cache was enabled.
+  pub misses: i64,
+  /// Number of cached passwords.
+  pub size: u32,
+}
+
+#[napi(object)]
+pub struct IdentifierPolicy {
+  /// Converts names to Unicode Normalization Form C.
+  pub normalize: Option<bool>,
+  /// Converts names to lowercase, so that names differing only in case refer to the same credential.
+  pub case_insensitive: Option<bool>,
+  /// Removes leading and trailing whitespace from names.
+  pub trim: Option<bool>,
+}
+
+/**
+ * Helper function to build the options for a single call, falling back to the configured defaults.
+ * Every call gets a cancel token, so that it can be cancelled through an `AbortSignal`,
+ * its timeout or the shutdown of the addon.
+ */
+pub fn to_options(env: Env, options: Option<KeytarOptions>) -> Result<Options> {
+  let options = options.unwrap_or(KeytarOptions {
+    allow_prompt: None,
+    signal: None,
+    timeout: None,
+  });
+
+  let cancel = CancelToken::new();
+  if let Some(signal) = options.signal {
+    link_signal(env, signal, &cancel)?;
+  }
+
+  Ok(
+    Options {
+      allow_prompt: options.allow_prompt,
+      cancel: Some(cancel),
+      timeout: options.timeout,
+      ..Options::default()
+    }
+    .with_defaults()
+    .start_timeout(),
+  )
+}
+
+/**
+ * Helper function to split the options of `findCredentials` into the options shared by all calls
+ * and the search that selects the credentials to return.
+ */
+pub fn to_search(
+  options: Option<FindCredentialsOptions>,
+) -> Result<(Option<KeytarOptions>, Search)> {
+  match options {
+    Some(options) => Ok((
+      Some(KeytarOptions {
+        allow_prompt: options.allow_prompt,
+        signal: options.signal,
+        timeout: options.timeout,
+      }),
+      Search {
+        sort: to_sort_key(options.sort)?,
+        dedupe: options.dedupe.unwrap_or(false),
+        page: Page {
+          offset: options.offset.unwrap_or(0) as usize,
+          limit: options.limit.map(|limit| limit as usize),
+        },
+      },
+    )),
+    None => Ok((None, Search::default())),
+  }
+}
+
+/// Options of `setPassword` that only apply to the credential being written.
+#[derive(Default)]
+pub struct WriteOptions {
+  /// Condition under which the password is written.
+  pub condition: Option<WriteCondition>,
+  /// Time in milliseconds since the Unix epoch at which the credential expires.
+  pub expires_at: Option<u64>,
+  pub tags: Option<HashMap<String, String>>,
+}
+
+/**
+ * Helper function to split the options of `setPassword` into the options shared by all calls
+ * and the options that only apply to the credential being written.
+ */
+pub fn to_write_options(
+  options: Option<SetPasswordOptions>,
+) -> Result<(Option<KeytarOptions>, WriteOptions)> {
+  let options = match options {
+    Some(options) => options,
+    None => return Ok((None, WriteOptions::default())),
+  };
+
+  let condition = match (options.if_absent.unwrap_or(false), options.if_match) {
+    (false, None) => None,
+    (true, None) => Some(WriteCondition::IfAbsent),
+    (false, Some(expected)) => Some(WriteCondition::IfMatch(Zeroizing::new(expected))),
+    (true, Some(_)) => {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Options 'ifAbsent' and 'ifMatch' cannot be combined".to_owned(),
+      ))
+    }
+  };
+
+  let expires_at = match (options.expires_at, options.ttl) {
+    (None, None) => None,
+    (Some(expires_at), None) if expires_at >= 0 => Some(expires_at as u64),
+    (None, Some(ttl)) if ttl >= 0 => Some(options::now_millis().saturating_add(ttl as u64)),
+    (Some(_), Some(_)) => {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Options 'expiresAt' and 'ttl' cannot be combined".to_owned(),
+      ))
+    }
+    _ => {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Options 'expiresAt' and 'ttl' must not be negative".to_owned(),
+      ))
+    }
+  };
+
+  if let Some(tags) = &options.tags {
+    if tags.keys().any(|name| name.is_empty()) {
+      return Err(Error::new(
+        Status::InvalidArg,
+        "Tag names must not be empty".to_owned(),
+      ));
+    }
+  }
+
+  Ok((
+    Some(KeytarOptions {
+      allow_prompt: options.allow_prompt,
+      signal: options.signal,
+      timeout: options.timeout,
+    }),
+    WriteOptions {
+      condition,
+      expires_at,
+      tags: options.tags,
+    },
+  ))
+}
+
+/**
+ * Helper function to parse the `sort` option of a search.
+ */
+pub fn to_sort_key(sort: Option<String>) -> Result<Option<SortKey>> {
+  match sort.as_deref() {
+    None => Ok(None),
+    Some("account") => Ok(Some(SortKey::Account)),
+    Some("created") => Ok(Some(SortKey::Created)),
+    Some("modified") => Ok(Some(SortKey::Modified)),
+    Some(sort) => Err(Error::new(
+      Status::InvalidArg,
+      format!(
+        "Invalid sort option '{}', expected 'account', 'created' or 'modified'",
+        sort
+      ),
+    )),
+  }
+}
+
+/**
+ * Helper function to list the names to search a service under: the name given by the identifier
+ * policy, followed by the name as given, which credentials stored before the policy was enabled may use.
+ */
+pub fn service_names(service: &String) -> Vec<String> {
+  let normalized_service = identifier::normalize(service).into_owned();
+  if normalized_service == *service {
+    vec![normalized_service]
+  } else {
+    vec![normalized_service, service.clone()]
+  }
+}
+
+/**
+ * Helper function to find credentials stored before the identifier policy was enabled,
+ * i.e. under account names that only match the given account once the policy is applied.
+ * Only the credentials of the service, under the names from `service_names`, are listed,
+ * so this is only done when a lookup misses.
+ * Returns: The service and account name of each such credential.
+ */
+fn legacy_names(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> std::result::Result<Vec<(String, String)>, KeytarError> {
+  if !identifier::is_enabled() {
+    return Ok(Vec::new());
+  }
+
+  let normalized_service = identifier::normalize(service).into_owned();
+  let normalized_account = identifier::normalize(account).into_owned();
+  let mut names: Vec<(String, String)> = Vec::new();
+  for name in service_names(service) {
+    let mut credentials: Vec<(String, Secret)> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    keytar::find_credentials(
+      std::slice::from_ref(&name),
+      &mut credentials,
+      &mut warnings,
+      &Search::default(),
+      options,
+    )?;
+    names.extend(
+      credentials
+        .into_iter()
+        .map(|(found_account, _)| found_account)
+        .filter(|found_account| {
+          (&name, found_account) != (&normalized_service, &normalized_account)
+            && identifier::normalize(found_account) == normalized_account.as_str()
+        })
+        .map(|found_account| (name.clone(), found_account)),
+    );
+  }
+  Ok(names)
+}
+
+/**
+ * Helper function to look up a credential under the names given by the identifier policy.
+ * Credentials stored before the policy was enabled are found under the names as given, or else
+ * under any names that normalize to the same ones, e.g. in decomposed form or in another case.
+ */
+pub fn with_legacy_names<T>(
+  service: &String,
+  account: &String,
+  options: &Options,
+  lookup: impl Fn(&String, &String) -> std::result::Result<Option<T>, KeytarError>,
+) -> std::result::Result<Option<T>, KeytarError> {
+  let normalized_service = identifier::normalize(service).into_owned();
+  let normalized_account = identifier::normalize(account).into_owned();
+  if let Some(result) = lookup(&normalized_service, &normalized_account)? {
+    return Ok(Some(result));
+  }
+  if (&normalized_service, &normalized_account) != (service, account) {
+    if let Some(result) = lookup(service, account)? {
+      return Ok(Some(result));
+    }
+  }
+
+  for (legacy_service, legacy_account) in legacy_names(service, account, options)? {
+    if let Some(result) = lookup(&legacy_service, &legacy_account)? {
+      return Ok(Some(result));
+    }
+  }
+  Ok(None)
+}
+
+/**
+ * Helper function to delete a credential under the names given by the identifier policy,
+ * and those stored before the policy was enabled. The backend deletes every version in their history with them.
+ * Returns: Whether a credential was deleted.
+ */
+fn delete_with_legacy_names(
+  service: &String,
+  account: &String,
+  options: &Options,
+) -> std::result::Result<bool, KeytarError> {
+  let normalized_service = identifier::normalize(service).into_owned();
+  let normalized_account = identifier::normalize(account).into_owned();
+  let mut deleted = keytar::delete_password(&normalized_service, &normalized_account, options)?;
+  for (legacy_service, legacy_account) in legacy_names(service, account, options)? {
+    deleted |= keytar::delete_password(&legacy_service, &legacy_account, options)?;
+  }
+  cache::invalidate(&normalized_service, &normalized_account);
+  Ok(deleted)
+}
+
+/// Abort listener added by `link_signal`, until the call that it cancels has settled.
+struct SignalLink {
+  cancel: CancelToken,
+  signal: Ref<()>,
+  listener: Ref<()>,
+}
+
+thread_local! {
+  /// Abort listeners of the calls started on this JavaScript thread that have not settled yet.
+  static SIGNAL_LINKS: RefCell<Vec<SignalLink>> = const { RefCell::new(Vec::new()) };
+}
+
+/**
+ * Helper function to cancel the given token when an `AbortSignal` aborts.
+ * The listener is kept until `unlink_signal` is called, so a long-lived signal does not collect
+ * a listener for every call it was passed to.
+ */
+pub fn link_signal(env: Env, signal: JsObject, cancel: &CancelToken) -> Result<()> {
+  if signal.get_named_property::<bool>("aborted")? {
+    cancel.cancel();
+    return Ok(());
+  }
+
+  let on_abort = {
+    let cancel = cancel.clone();
+    env.create_function_from_closure("onabort", move |_ctx| {
+      cancel.cancel();
+      Ok(())
+    })?
+  };
+  let mut listener_options = env.create_object()?;
+  listener_options.set_named_property("once", true)?;
+
+  let add_event_listener: JsFunction = signal.get_named_property("addEventListener")?;
+  add_event_listener.call(
+    Some(&signal),
+    &[
+      // The listener is kept as well, to remove it later
+      unsafe { JsUnknown::from_raw_unchecked(env.raw(), on_abort.raw()) },
+      listener_options.into_unknown(),
+    ],
+  )?;
+
+  let link = SignalLink {
+    cancel: cancel.clone(),
+    signal: env.create_reference(signal)?,
+    listener: env.create_reference(on_abort)?,
+  };
+  SIGNAL_LINKS.with(|links| links.borrow_mut().push(link));
+  Ok(())
+}
+
+/**
+ * Helper function to remove the abort listeners that `link_signal` added for the given token,
+ * once the call it cancels has settled.
+ * Listeners of tokens that were cancelled meanwhile are removed as well, as nothing waits for them.
+ */
+pub fn unlink_signal(env: Env, cancel: &CancelToken) -> Result<()> {
+  let links: Vec<SignalLink> = SIGNAL_LINKS.with(|links| {
+    let mut links = links.borrow_mut();
+    let (unlinked, kept) = std::mem::take(&mut *links)
+      .into_iter()
+      .partition(|link| link.cancel.same_as(cancel) || link.cancel.is_cancelled());
+    *links = kept;
+    unlinked
+  });
+
+  let mut result = Ok(());
+  for mut link in links {
+    let removed = (|| {
+      let signal: JsObject = env.get_reference_value(&link.signal)?;
+      let listener: JsFunction = env.get_reference_value(&link.listener)?;
+      let remove_event_listener: JsFunction = signal.get_named_property("removeEventListener")?;
+      remove_event_listener.call(Some(&signal), &[listener])?;
+      Ok(())
+    })();
+    // The references are released in any case, even if the listener could not be removed
+    link.signal.unref(env)?;
+    link.listener.unref(env)?;
+    result = result.and(removed);
+  }
+
+  result
+}
+
+/**
+ * Helper function to convert a backend error into a JS error.
+ * The error's `code` and `name` properties are set from `KeytarError::code` and `KeytarError::name`.
+ */
+pub fn to_js_error(env: Env, err: KeytarError) -> Error {
+  let js_error = env
+    .create_error(Error::from_reason(err.to_string()))
+    .and_then(|mut js_error| {
+      js_error.set_named_property("code", env.create_string(err.code())?)?;
+      js_error.set_named_property("name", env.create_string(err.name())?)?;
+      Ok(js_error)
+    });
+
+  match js_error {
+    Ok(js_error) => Error::from(js_error.into_unknown()),
+    Err(e) => e,
+  }
+}
+
 #[napi]
 impl Task for GetPassword {
-  type Output = Option<String>;
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
   type JsValue = JsUnknown;
 
   fn compute(&mut self) -> Result<Self::Output> {
-    match keytar::get_password(&self.service, &self.account) {
-      Ok(pw) => Ok(pw),
-      Err(err) => Err(napi::Error::from_reason(err.to_string())),
-    }
+    Ok(self.options.run(|| {
+      cache::get_password(&self.service, &self.account, &self.options, || {
+        with_legacy_names(
+          &self.service,
+          &self.account,
+          &self.options,
+          |service, account| keytar::get_credential(service, account, &self.options),
+        )
+      })
+    }))
   }
 
   fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
     Ok(match output {
-      Some(pw) => env.create_string(pw.as_str())?.into_unknown(),
-      None => env.get_null()?.into_unknown()
+      Ok(Some(pw)) => env.create_string(pw.as_str())?.into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
     })
   }
 
@@ -57,19 +660,72 @@ impl Task for GetPassword {
 }
 
 #[napi]
-impl Task for SetPassword {
-  type Output = bool;
-  type JsValue = JsUnknown;
+impl Task for VerifyPassword {
+  type Output = std::result::Result<bool, KeytarError>;
+  type JsValue = JsBoolean;
 
   fn compute(&mut self) -> Result<Self::Output> {
-    match keytar::set_password(&self.service, &self.account, &mut self.password) {
-      Ok(result) => Ok(result),
-      Err(err) => Err(napi::Error::from_reason(err.to_string())),
+    Ok(self.options.run(|| {
+      let stored = cache::get_password(&self.service, &self.account, &self.options, || {
+        with_legacy_names(
+          &self.service,
+          &self.account,
+          &self.options,
+          |service, account| keytar::get_credential(service, account, &self.options),
+        )
+      })?;
+      Ok(match stored {
+        Some(stored) => secrets_equal(&stored, &self.candidate),
+        None => false,
+      })
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(matches) => env.get_boolean(matches),
+      Err(err) => Err(to_js_error(env, err)),
     }
   }
 
-  fn resolve(&mut self, env: Env, _output: Self::Output) -> Result<Self::JsValue> {
-    Ok(env.get_null()?.into_unknown())
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for SetPassword {
+  type Output = std::result::Result<bool, KeytarError>;
+  type JsValue = JsBoolean;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let service = identifier::normalize(&self.service).into_owned();
+      let account = identifier::normalize(&self.account).into_owned();
+      // Hold the lock between checking the condition and writing,
+      // so no other call of this process changes the password in between
+      let _lock = lock::lock(&service, &account);
+      if let Some(condition) = &self.condition {
+        let current = with_legacy_names(
+          &self.service,
+          &self.account,
+          &self.options,
+          |service, account| keytar::get_password(service, account, &self.options),
+        )?;
+        if !condition.is_met(current.as_ref().map(|current| current.as_str())) {
+          return Ok(false);
+        }
+      }
+
+      history::write(&service, &account, &mut self.password, &self.options)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(written) => env.get_boolean(written),
+      Err(err) => Err(to_js_error(env, err)),
+    }
   }
 
   fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
@@ -79,18 +735,60 @@ impl Task for SetPassword {
 
 #[napi]
 impl Task for DeletePassword {
-  type Output = bool;
+  type Output = std::result::Result<bool, KeytarError>;
   type JsValue = JsBoolean;
 
   fn compute(&mut self) -> Result<Self::Output> {
-    match keytar::delete_password(&self.service, &self.account) {
-      Ok(result) => Ok(result),
-      Err(err) => Err(napi::Error::from_reason(err.to_string())),
+    Ok(self.options.run(|| {
+      let _lock = lock::lock(
+        &identifier::normalize(&self.service),
+        &identifier::normalize(&self.account),
+      );
+      delete_with_legacy_names(&self.service, &self.account, &self.options)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(result) => env.get_boolean(result),
+      Err(err) => Err(to_js_error(env, err)),
     }
   }
 
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for SwapPassword {
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let service = identifier::normalize(&self.service).into_owned();
+      let account = identifier::normalize(&self.account).into_owned();
+      // Hold the lock between reading and writing,
+      // so no other call of this process changes the password in between
+      let _lock = lock::lock(&service, &account);
+      let previous = with_legacy_names(
+        &self.service,
+        &self.account,
+        &self.options,
+        |service, account| keytar::get_password(service, account, &self.options),
+      )?;
+      history::write(&service, &account, &mut self.password, &self.options)?;
+      Ok(previous)
+    }))
+  }
+
   fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
-    env.get_boolean(output)
+    Ok(match output {
+      Ok(Some(pw)) => env.create_string(pw.as_str())?.into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
+    })
   }
 
   fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
@@ -99,25 +797,181 @@ impl Task for DeletePassword {
 }
 
 #[napi]
-impl Task for FindCredentials {
-  type Output = Vec<(String, String)>;
-  type JsValue = Vec<Credential>;
+impl Task for TakePassword {
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
+  type JsValue = JsUnknown;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      // Hold the lock between reading and deleting,
+      // so no other call of this process reads or changes the password in between
+      let _lock = lock::lock(
+        &identifier::normalize(&self.service),
+        &identifier::normalize(&self.account),
+      );
+      let password = with_legacy_names(
+        &self.service,
+        &self.account,
+        &self.options,
+        |service, account| keytar::get_password(service, account, &self.options),
+      )?;
+      if password.is_some() {
+        delete_with_legacy_names(&self.service, &self.account, &self.options)?;
+      }
+      Ok(password)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(match output {
+      Ok(Some(pw)) => env.create_string(pw.as_str())?.into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
+    })
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for GetPasswordHistory {
+  type Output = std::result::Result<Vec<(u32, Secret)>, KeytarError>;
+  type JsValue = JsObject;
 
   fn compute(&mut self) -> Result<Self::Output> {
-    let mut credentials: Self::Output = Vec::new();
-    match keytar::find_credentials(&self.service, &mut credentials) {
-      Ok(_result) => Ok(credentials),
-      Err(err) => Err(napi::Error::from_reason(err.to_string())),
+    Ok(self.options.run(|| {
+      history::read(
+        &identifier::normalize(&self.service).into_owned(),
+        &identifier::normalize(&self.account).into_owned(),
+        &self.options,
+      )
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let versions = output.map_err(|err| to_js_error(env, err))?;
+
+    let mut js_versions = env.create_array_with_length(versions.len())?;
+    for (i, (version, password)) in versions.iter().enumerate() {
+      let mut obj = env.create_object()?;
+      obj.set_named_property("version", env.create_uint32(*version)?)?;
+      obj.set_named_property("password", env.create_string(password)?)?;
+      js_versions.set_element(i as u32, obj)?;
     }
+
+    Ok(js_versions)
   }
 
-  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
-    let mut creds = Vec::new();
-    for cred in output {
-      creds.push(Credential {
-        account: cred.0,
-        password: cred.1,
-      })
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for RollbackPassword {
+  type Output = std::result::Result<bool, KeytarError>;
+  type JsValue = JsBoolean;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let service = identifier::normalize(&self.service).into_owned();
+      let account = identifier::normalize(&self.account).into_owned();
+      let _lock = lock::lock(&service, &account);
+      let version = Options {
+        version: Some(self.version),
+        ..self.options.clone()
+      };
+      match keytar::get_password(&service, &account, &version)? {
+        // The replaced password becomes version 1, so it can be rolled back in turn
+        Some(mut password) => history::write(&service, &account, &mut password, &self.options),
+        None => Ok(false),
+      }
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(rolled_back) => env.get_boolean(rolled_back),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for PurgeExpired {
+  type Output = std::result::Result<u32, KeytarError>;
+  type JsValue = JsNumber;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    Ok(self.options.run(|| {
+      let purged = match &self.service {
+        Some(service) => {
+          let normalized = identifier::normalize(service).into_owned();
+          let mut purged = keytar::purge_expired(Some(&normalized), &self.options)?;
+          // Also purge the credentials stored before the identifier policy was enabled
+          if &normalized != service {
+            purged += keytar::purge_expired(Some(service), &self.options)?;
+          }
+          purged
+        }
+        None => keytar::purge_expired(None, &self.options)?,
+      };
+
+      // Passwords cached before their credentials expired are dropped with them
+      cache::clear();
+      Ok(purged)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    match output {
+      Ok(purged) => env.create_uint32(purged),
+      Err(err) => Err(to_js_error(env, err)),
+    }
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for SearchCredentials {
+  type Output = std::result::Result<Vec<Tagged>, KeytarError>;
+  type JsValue = JsObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let mut attributes = self.attributes.clone();
+    for name in ["service", "account"] {
+      if let Some(value) = attributes.get_mut(name) {
+        *value = identifier::normalize(value).into_owned();
+      }
+    }
+
+    Ok(
+      self
+        .options
+        .run(|| keytar::search_credentials(&attributes, &self.tags, &self.options)),
+    )
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let credentials = output.map_err(|err| to_js_error(env, err))?;
+
+    let mut creds = env.create_array_with_length(credentials.len())?;
+    for (i, credential) in credentials.into_iter().enumerate() {
+      let mut obj = env.create_object()?;
+      obj.set_named_property("service", env.create_string(&credential.service)?)?;
+      obj.set_named_property("account", env.create_string(&credential.account)?)?;
+      obj.set_named_property("password", env.create_string(&credential.password)?)?;
+      obj.set_named_property("tags", credential.tags)?;
+      creds.set_element(i as u32, obj)?;
     }
 
     Ok(creds)
@@ -128,22 +982,127 @@ impl Task for FindCredentials {
   }
 }
 
+#[napi]
+impl Task for FindByAccount {
+  type Output = std::result::Result<Vec<(String, String, Option<Secret>)>, KeytarError>;
+  type JsValue = JsObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let account = identifier::normalize(&self.account).into_owned();
+    Ok(self.options.run(|| {
+      let mut credentials =
+        keytar::find_by_account(&account, self.prefix, self.secrets, &self.options)?;
+
+      // Include credentials stored before the identifier policy was enabled
+      if account != self.account {
+        let mut legacy_credentials =
+          keytar::find_by_account(&self.account, self.prefix, self.secrets, &self.options)?;
+        legacy_credentials.retain(|(service, account, _)| {
+          !credentials
+            .iter()
+            .any(|(s, a, _)| s == service && a == account)
+        });
+        credentials.append(&mut legacy_credentials);
+      }
+
+      Ok(credentials)
+    }))
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let credentials = output.map_err(|err| to_js_error(env, err))?;
+
+    let mut creds = env.create_array_with_length(credentials.len())?;
+    for (i, (service, account, password)) in credentials.iter().enumerate() {
+      let mut obj = env.create_object()?;
+      obj.set_named_property("service", env.create_string(service)?)?;
+      obj.set_named_property("account", env.create_string(account)?)?;
+      if let Some(password) = password {
+        obj.set_named_property("password", env.create_string(password)?)?;
+      }
+      creds.set_element(i as u32, obj)?;
+    }
+
+    Ok(creds)
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
+#[napi]
+impl Task for FindCredentials {
+  type Output = std::result::Result<(Vec<(String, Secret)>, Vec<String>), KeytarError>;
+  type JsValue = JsObject;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    let mut credentials: Vec<(String, Secret)> = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    Ok(
+      self
+        .options
+        .run(|| {
+          keytar::find_credentials(
+            &service_names(&self.service),
+            &mut credentials,
+            &mut warnings,
+            &self.search,
+            &self.options,
+          )?;
+          Ok(())
+        })
+        .map(|_| (credentials, warnings)),
+    )
+  }
+
+  fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    let (credentials, warnings) = output.map_err(|err| to_js_error(env, err))?;
+
+    let mut creds = env.create_array_with_length(credentials.len())?;
+    for (i, cred) in credentials.iter().enumerate() {
+      let mut obj = env.create_object()?;
+      obj.set_named_property("account", env.create_string(&cred.0)?)?;
+      obj.set_named_property("password", env.create_string(&cred.1)?)?;
+      creds.set_element(i as u32, obj)?;
+    }
+
+    // Skipped items are reported through a non-enumerable property, so the result still
+    // compares and serializes like a plain array of credentials
+    let mut js_warnings = env.create_array_with_length(warnings.len())?;
+    for (i, warning) in warnings.into_iter().enumerate() {
+      js_warnings.set_element(i as u32, env.create_string(&warning)?)?;
+    }
+    creds.define_properties(&[Property::new("warnings")?.with_value(&js_warnings)])?;
+
+    Ok(creds)
+  }
+
+  fn reject(&mut self, _env: Env, err: Error) -> Result<Self::JsValue> {
+    Err(err)
+  }
+}
+
 #[napi]
 impl Task for FindPassword {
-  type Output = Option<String>;
+  type Output = std::result::Result<Option<Secret>, KeytarError>;
   type JsValue = JsUnknown;
 
   fn compute(&mut self) -> Result<Self::Output> {
-    match keytar::find_password(&self.service) {
-      Ok(pw) => Ok(pw),
-      Err(err) => Err(napi::Error::from_reason(err.to_string())),
-    }
+    Ok(self.options.run(|| {
+      let service = identifier::normalize_joined(&self.service).into_owned();
+      match keytar::find_password(&service, &self.options)? {
+        None if service != self.service => keytar::find_password(&self.service, &self.options),
+        result => Ok(result),
+      }
+    }))
   }
 
   fn resolve(&mut self, env: Env, output: Self::Output) -> Result<Self::JsValue> {
     Ok(match output {
-      Some(pw) => env.create_string(pw.as_str())?.into_unknown(),
-      None => env.get_null()?.into_unknown()
+      Ok(Some(pw)) => env.create_string(pw.as_str())?.into_unknown(),
+      Ok(None) => env.get_null()?.into_unknown(),
+      Err(err) => return Err(to_js_error(env, err)),
     })
   }
 